@@ -0,0 +1,37 @@
+mod raw;
+
+use crate::mdl::AnimationBlock;
+use crate::{ModelError, Readable};
+pub use raw::AniHeader;
+
+type Result<T> = std::result::Result<T, ModelError>;
+
+/// The ani file contains the animation blocks of an mdl, for animation data that isn't stored in the mdl itself
+#[derive(Debug, Clone)]
+pub struct Ani {
+    pub header: AniHeader,
+    data: Vec<u8>,
+}
+
+impl Ani {
+    pub fn read(data: &[u8]) -> Result<Self> {
+        let header = <AniHeader as Readable>::read(data)?;
+        Ok(Ani {
+            header,
+            data: data.to_vec(),
+        })
+    }
+
+    /// Get the data of an animation block
+    pub fn block(&self, block: &AnimationBlock) -> Result<&[u8]> {
+        let start = usize::try_from(block.data_start).ok();
+        let end = usize::try_from(block.data_end).ok();
+        start
+            .zip(end)
+            .and_then(|(start, end)| self.data.get(start..end))
+            .ok_or(ModelError::OutOfBounds {
+                data: "AnimationBlock",
+                offset: block.data_start as usize,
+            })
+    }
+}
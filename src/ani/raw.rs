@@ -0,0 +1,14 @@
+use bytemuck::{Pod, Zeroable};
+use std::mem::size_of;
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct AniHeader {
+    pub id: i32,
+    pub version: i32,
+    pub checksum: [u8; 4], // has to match the checksum of the mdl file
+    pub name: [u8; 64],
+    pub data_length: i32,
+}
+
+static_assertions::const_assert_eq!(size_of::<AniHeader>(), 80);
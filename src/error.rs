@@ -11,6 +11,18 @@ pub enum ModelError {
     OutOfBounds { data: &'static str, offset: usize },
     #[error("Trying to read past the end of the file")]
     Eof(usize),
+    #[error("bone {bone} has an out of bounds parent {parent}")]
+    InvalidBoneParent { bone: usize, parent: i32 },
+    #[error("bone {0} is part of a cycle in the bone hierarchy")]
+    BoneCycle(usize),
+    #[error("ik chain {chain} references bone {bone} which doesn't exist")]
+    InvalidIkChainBone { chain: usize, bone: i32 },
+    #[error("ik lock {lock} references ik chain {chain} which doesn't exist")]
+    InvalidIkLockChain { lock: usize, chain: i32 },
+    #[error("invalid keyvalues at {offset}: {reason}")]
+    InvalidKeyValues { offset: usize, reason: &'static str },
+    #[error("animation data is stored in animation block {0} which hasn't been loaded")]
+    AnimationBlockNotLoaded(i32),
 }
 
 #[derive(Debug, Error)]
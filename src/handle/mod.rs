@@ -7,10 +7,21 @@ use std::ops::Deref;
 /// reference parts from other structures in the mdl file
 #[derive(Debug)]
 pub struct Handle<'a, T> {
-    _mdl: &'a Mdl,
+    mdl: &'a Mdl,
     data: &'a T,
 }
 
+impl<'a, T> Handle<'a, T> {
+    pub(crate) fn new(mdl: &'a Mdl, data: &'a T) -> Self {
+        Handle { mdl, data }
+    }
+
+    /// The mdl file containing the structure
+    pub fn mdl(&self) -> &'a Mdl {
+        self.mdl
+    }
+}
+
 impl<T> Clone for Handle<'_, T> {
     fn clone(&self) -> Self {
         Handle { ..*self }
@@ -1,3 +1,4 @@
+pub mod ani;
 mod error;
 mod handle;
 pub mod mdl;
@@ -5,79 +6,458 @@ mod shared;
 pub mod vtx;
 pub mod vvd;
 
+pub use crate::ani::Ani;
 pub use crate::mdl::Mdl;
+use crate::mdl::{BodyGroupSelection, HitboxHit, Ray};
 pub use crate::vtx::Vtx;
-use crate::vvd::Vertex;
 pub use crate::vvd::Vvd;
+use crate::vvd::{SkinnedVertex, Vertex};
 use bytemuck::{pod_read_unaligned, Pod};
+use cgmath::{Matrix4, Point3};
 pub use error::*;
 pub use handle::Handle;
+use itertools::Itertools;
 pub use shared::*;
 use std::any::type_name;
 use std::mem::size_of;
 
 pub struct Model {
-    #[allow(dead_code)]
     mdl: Mdl,
     vtx: Vtx,
     vvd: Vvd,
+    skin: usize,
 }
 
 impl Model {
     pub fn from_parts(mdl: Mdl, vtx: Vtx, vvd: Vvd) -> Self {
-        Model { mdl, vtx, vvd }
+        Model {
+            mdl,
+            vtx,
+            vvd,
+            skin: 0,
+        }
+    }
+
+    pub fn mdl(&self) -> &Mdl {
+        &self.mdl
+    }
+
+    /// Use a different skin family for the materials of the model's meshes
+    pub fn with_skin(self, skin: usize) -> Self {
+        Model { skin, ..self }
+    }
+
+    pub fn skin(&self) -> usize {
+        self.skin
+    }
+
+    pub fn skin_count(&self) -> usize {
+        self.mdl.skin_table.family_count()
+    }
+
+    /// Name of the material used by a mesh in the specified skin
+    pub fn material_for_mesh(&self, mesh: &ModelMesh, skin: usize) -> Option<&str> {
+        self.mdl
+            .mesh_texture(&mesh.mesh, skin)
+            .map(|texture| texture.name.as_str())
     }
 
     pub fn vertex_strips(&self) -> impl Iterator<Item = impl Iterator<Item = &'_ Vertex> + '_> {
-        self.vertex_strip_indices()
-            .map(|strip| strip.map(|index| &self.vvd.vertices[index]))
+        self.lod(0).vertex_strips()
     }
 
     pub fn vertices(&self) -> &[Vertex] {
-        &self.vvd.vertices
+        self.lod(0).vertices()
+    }
+
+    pub fn body_parts(&self) -> &[mdl::BodyPart] {
+        &self.mdl.body_parts
+    }
+
+    /// Unpack the `body` value as used by the engine into a body group selection for this model
+    pub fn body_group_selection(&self, body: i32) -> BodyGroupSelection {
+        BodyGroupSelection::from_body(&self.mdl.body_parts, body)
+    }
+
+    /// Iterate over all meshes of the model, including every model of each body part
+    pub fn meshes(&self) -> impl Iterator<Item = ModelMesh<'_>> {
+        self.lod(0).meshes()
+    }
+
+    /// Iterate over the meshes of the selected model for each body part
+    pub fn selected_meshes<'a>(
+        &'a self,
+        selection: &'a BodyGroupSelection,
+    ) -> impl Iterator<Item = ModelMesh<'a>> {
+        self.lod(0).selected_meshes(selection)
     }
 
     pub fn vertex_strip_indices(&self) -> impl Iterator<Item = impl Iterator<Item = usize> + '_> {
-        let mesh_vertex_offsets = self
+        self.lod(0).vertex_strip_indices()
+    }
+
+    /// Vertex indices for the strips of the selected model for each body part
+    pub fn selected_vertex_strip_indices<'a>(
+        &'a self,
+        selection: &'a BodyGroupSelection,
+    ) -> impl Iterator<Item = impl Iterator<Item = usize> + 'a> {
+        self.lod(0).selected_vertex_strip_indices(selection)
+    }
+
+    /// Vertex indices for each triangle of the selected model for each body part
+    pub fn selected_triangles<'a>(
+        &'a self,
+        selection: &'a BodyGroupSelection,
+    ) -> impl Iterator<Item = [usize; 3]> + 'a {
+        self.lod(0).selected_triangles(selection)
+    }
+
+    /// Find the closest hitbox from a hitbox set that is hit by a ray
+    ///
+    /// The world transforms of the bones can be provided to test against a posed model,
+    /// the bind pose is used otherwise
+    pub fn intersect_hitboxes(
+        &self,
+        hitbox_set: usize,
+        ray: &Ray,
+        bones: Option<&[Matrix4<f32>]>,
+    ) -> Result<Option<HitboxHit>, ModelError> {
+        let Some(set) = self.mdl.hitbox_sets.get(hitbox_set) else {
+            return Ok(None);
+        };
+        Ok(match bones {
+            Some(bones) => set.intersect_ray(bones, ray),
+            None => set.intersect_ray(&self.mdl.bind_pose()?.world, ray),
+        })
+    }
+
+    /// Find the first hitbox from a hitbox set that contains a point
+    ///
+    /// The world transforms of the bones can be provided to test against a posed model,
+    /// the bind pose is used otherwise
+    pub fn hitbox_at_point(
+        &self,
+        hitbox_set: usize,
+        point: Point3<f32>,
+        bones: Option<&[Matrix4<f32>]>,
+    ) -> Result<Option<usize>, ModelError> {
+        let Some(set) = self.mdl.hitbox_sets.get(hitbox_set) else {
+            return Ok(None);
+        };
+        Ok(match bones {
+            Some(bones) => set.hitbox_at_point(bones, point),
+            None => set.hitbox_at_point(&self.mdl.bind_pose()?.world, point),
+        })
+    }
+
+    /// Transform the vertices into a pose of the model using linear blend skinning
+    ///
+    /// `bones` are the world transforms of every bone in the pose, as returned by
+    /// [`Skeleton::world_transforms`](mdl::Skeleton::world_transforms)
+    pub fn skin_vertices(&self, bones: &[Matrix4<f32>]) -> Vec<SkinnedVertex> {
+        self.lod(0).skin_vertices(bones)
+    }
+
+    /// Get the vertices with the morph targets of every mesh applied
+    ///
+    /// `flex_weights` are the weights for each flex descriptor, as returned by [`Mdl::flex_weights`]
+    pub fn flex_vertices(&self, flex_weights: &[f32]) -> Vec<Vertex> {
+        self.lod(0).flex_vertices(flex_weights)
+    }
+
+    pub fn lod_count(&self) -> usize {
+        self.vtx.header.lod_count.max(1) as usize
+    }
+
+    /// The highest detail lod that can be used by the model
+    pub fn root_lod(&self) -> usize {
+        let root_lod = self.mdl.header.root_lod as usize;
+        let root_lod = match self.mdl.header.num_allowed_root_lods as usize {
+            0 => root_lod,
+            allowed => root_lod.min(allowed - 1),
+        };
+        root_lod.min(self.lod_count() - 1)
+    }
+
+    /// Get the geometry for a level of detail
+    ///
+    /// The lod is clamped to the range of lods available for the model
+    pub fn lod(&self, lod: usize) -> Lod<'_> {
+        let lod = lod.clamp(self.root_lod(), self.lod_count() - 1);
+        // the vertices are truncated to the vertices used by the lod,
+        // but only if the lod is allowed to be used as root lod
+        let root_lod = match self.mdl.header.num_allowed_root_lods as usize {
+            0 => lod,
+            allowed => lod.min(allowed - 1),
+        };
+        let root_lod = if self.vvd.header.has_fixups() && self.vvd.lod_vertices(root_lod).is_some()
+        {
+            root_lod
+        } else {
+            0
+        };
+        Lod {
+            model: self,
+            lod,
+            root_lod,
+        }
+    }
+
+    /// Find the lod to use for a lod metric, skipping the shadow lod
+    ///
+    /// The metric is compared to the switch points of the lods, which roughly correspond to the
+    /// distance from the camera to the model
+    pub fn lod_for_metric(&self, metric: f32) -> usize {
+        let Some(vtx_model) = self
+            .vtx
+            .body_parts
+            .first()
+            .and_then(|part| part.models.first())
+        else {
+            return 0;
+        };
+        let lods = &vtx_model.lods;
+        let lod_count = match lods.last() {
+            Some(lod) if lod.switch_point < 0.0 => lods.len() - 1,
+            _ => lods.len(),
+        };
+        let root_lod = self.root_lod();
+        (root_lod..lod_count.saturating_sub(1))
+            .find(|lod| lods[lod + 1].switch_point > metric)
+            .unwrap_or(lod_count.saturating_sub(1).max(root_lod))
+    }
+}
+
+/// The geometry of a model at a specific level of detail
+#[derive(Clone, Copy)]
+pub struct Lod<'a> {
+    model: &'a Model,
+    lod: usize,
+    root_lod: usize,
+}
+
+impl<'a> Lod<'a> {
+    /// Index of the lod
+    pub fn index(&self) -> usize {
+        self.lod
+    }
+
+    /// The vertices indexed by the meshes of this lod
+    pub fn vertices(self) -> &'a [Vertex] {
+        self.model
+            .vvd
+            .lod_vertices(self.root_lod)
+            .unwrap_or(&self.model.vvd.vertices)
+    }
+
+    /// Transform the vertices of the lod into a pose of the model using linear blend skinning
+    pub fn skin_vertices(self, bones: &[Matrix4<f32>]) -> Vec<SkinnedVertex> {
+        let skinning: Vec<Matrix4<f32>> = self
+            .model
+            .mdl
+            .bones
+            .iter()
+            .zip(bones)
+            .map(|(bone, world)| world * Matrix4::from(bone.pose_to_bone))
+            .collect();
+        self.vertices()
+            .iter()
+            .map(|vertex| vertex.skin(&skinning))
+            .collect()
+    }
+
+    /// Get the vertices of the lod with the morph targets of every mesh applied
+    pub fn flex_vertices(self, flex_weights: &[f32]) -> Vec<Vertex> {
+        let mut vertices = self.vertices().to_vec();
+        for mesh in self.meshes() {
+            for flex in &mesh.mesh.flexes {
+                for flex_vertex in &flex.vertices {
+                    let weight = flex.vertex_weight(flex_vertex, flex_weights);
+                    let index = mesh.vertex_offset + flex_vertex.index;
+                    let Some(vertex) = vertices.get_mut(index).filter(|_| weight != 0.0) else {
+                        continue;
+                    };
+                    vertex.position = vertex.position + flex_vertex.position * weight;
+                    vertex.normal = vertex.normal + flex_vertex.normal * weight;
+                }
+            }
+        }
+        vertices
+    }
+
+    pub fn vertex_strips(self) -> impl Iterator<Item = impl Iterator<Item = &'a Vertex> + 'a> + 'a {
+        let vertices = self.vertices();
+        self.vertex_strip_indices()
+            .map(move |strip| strip.map(move |index| &vertices[index]))
+    }
+
+    /// Iterate over all meshes of the lod, including every model of each body part
+    pub fn meshes(self) -> impl Iterator<Item = ModelMesh<'a>> + 'a {
+        self.filtered_meshes(None)
+    }
+
+    /// Iterate over the meshes of the selected model for each body part
+    pub fn selected_meshes(
+        self,
+        selection: &'a BodyGroupSelection,
+    ) -> impl Iterator<Item = ModelMesh<'a>> + 'a {
+        self.filtered_meshes(Some(selection))
+    }
+
+    fn filtered_meshes(
+        self,
+        selection: Option<&'a BodyGroupSelection>,
+    ) -> impl Iterator<Item = ModelMesh<'a>> + 'a {
+        let Lod {
+            model,
+            lod,
+            root_lod,
+        } = self;
+        // when the vertices are truncated for the root lod, the vertex offsets need to be re-calculated
+        let truncated = root_lod > 0;
+        let models = model
             .mdl
             .body_parts
             .iter()
-            .flat_map(|part| part.models.iter())
-            .flat_map(|model| {
-                model
-                    .meshes
+            .zip(model.vtx.body_parts.iter())
+            .enumerate()
+            .flat_map(|(body_part, (mdl_part, vtx_part))| {
+                mdl_part
+                    .models
                     .iter()
-                    .map(move |mesh| (mesh.vertex_offset + model.vertex_offset) as usize)
+                    .zip(vtx_part.models.iter())
+                    .enumerate()
+                    .map(move |(model, models)| (body_part, model, models))
             });
 
-        let vtx_meshes = self
-            .vtx
-            .body_parts
-            .iter()
-            .flat_map(|part| part.models.iter())
-            .flat_map(|model| model.lods.first())
-            .flat_map(|lod| lod.meshes.iter());
-
-        vtx_meshes
-            .zip(mesh_vertex_offsets)
-            .flat_map(|(vtx_mesh, vertex_offset)| {
-                vtx_mesh
-                    .strip_groups
+        models
+            .scan(0, move |model_offset, (body_part, model, models)| {
+                let (mdl_model, _) = models;
+                let vertex_offset = if truncated {
+                    *model_offset
+                } else {
+                    mdl_model.vertex_offset as usize
+                };
+                *model_offset += mdl_model
+                    .meshes
                     .iter()
-                    .map(move |strip_group| (strip_group, vertex_offset))
+                    .map(|mesh| mesh.vertex_count(root_lod))
+                    .sum::<usize>();
+                Some((body_part, model, models, vertex_offset))
+            })
+            .filter(move |(body_part, model, _, _)| match selection {
+                Some(selection) => selection.model(*body_part) == *model,
+                None => true,
             })
-            .flat_map(|(strip_group, mesh_vertex_offset)| {
-                let group_indices = &strip_group.indices;
-                let vertices = &strip_group.vertices;
-                strip_group.strips.iter().map(move |strip| {
-                    strip
-                        .indices()
-                        .map(move |index| group_indices[index] as usize)
-                        .map(move |index| {
-                            vertices[index].original_mesh_vertex_id as usize + mesh_vertex_offset
-                        })
-                })
+            .flat_map(
+                move |(body_part, model_index, (mdl_model, vtx_model), model_offset)| {
+                    let vtx_meshes = vtx_model
+                        .lods
+                        .get(lod)
+                        .map(|lod| lod.meshes.as_slice())
+                        .unwrap_or_default();
+                    mdl_model.meshes.iter().zip(vtx_meshes).scan(
+                        0,
+                        move |mesh_offset, (mdl_mesh, vtx_mesh)| {
+                            let vertex_offset = if truncated {
+                                *mesh_offset
+                            } else {
+                                mdl_mesh.vertex_offset as usize
+                            };
+                            *mesh_offset += mdl_mesh.vertex_count(root_lod);
+                            Some(ModelMesh {
+                                mesh: Handle::new(&model.mdl, mdl_mesh),
+                                vtx: vtx_mesh,
+                                body_part,
+                                model: model_index,
+                                vertex_offset: model_offset + vertex_offset,
+                                skin: model.skin,
+                            })
+                        },
+                    )
+                },
+            )
+    }
+
+    pub fn vertex_strip_indices(
+        self,
+    ) -> impl Iterator<Item = impl Iterator<Item = usize> + 'a> + 'a {
+        self.meshes().flat_map(|mesh| mesh.vertex_strip_indices())
+    }
+
+    /// Vertex indices for the strips of the selected model for each body part
+    pub fn selected_vertex_strip_indices(
+        self,
+        selection: &'a BodyGroupSelection,
+    ) -> impl Iterator<Item = impl Iterator<Item = usize> + 'a> + 'a {
+        self.selected_meshes(selection)
+            .flat_map(|mesh| mesh.vertex_strip_indices())
+    }
+
+    /// Vertex indices for each triangle of the selected model for each body part
+    pub fn selected_triangles(
+        self,
+        selection: &'a BodyGroupSelection,
+    ) -> impl Iterator<Item = [usize; 3]> + 'a {
+        self.selected_meshes(selection)
+            .flat_map(|mesh| mesh.triangles())
+    }
+}
+
+/// A single mesh of a model, combining the mdl and vtx data for the mesh
+#[derive(Debug, Clone)]
+pub struct ModelMesh<'a> {
+    pub mesh: Handle<'a, mdl::Mesh>,
+    pub vtx: &'a vtx::Mesh,
+    /// Index of the body part containing the mesh
+    pub body_part: usize,
+    /// Index of the model containing the mesh within the body part
+    pub model: usize,
+    /// Offset of the mesh's first vertex in the model vertices
+    pub vertex_offset: usize,
+    skin: usize,
+}
+
+impl<'a> ModelMesh<'a> {
+    /// Name of the material used by the mesh in the model's current skin
+    pub fn material(&self) -> Option<&'a str> {
+        self.mesh
+            .texture(self.skin)
+            .map(|texture| texture.name.as_str())
+    }
+
+    pub fn vertex_strip_indices(&self) -> impl Iterator<Item = impl Iterator<Item = usize> + 'a> {
+        let vertex_offset = self.vertex_offset;
+        self.vtx.strip_groups.iter().flat_map(move |strip_group| {
+            let group_indices = &strip_group.indices;
+            let vertices = &strip_group.vertices;
+            strip_group.strips.iter().map(move |strip| {
+                strip
+                    .indices()
+                    .map(move |index| group_indices[index] as usize)
+                    .map(move |index| {
+                        vertices[index].original_mesh_vertex_id as usize + vertex_offset
+                    })
             })
+        })
+    }
+
+    /// Whether the mesh is rendered as an eye
+    pub fn is_eyes(&self) -> bool {
+        self.vtx.flags.contains(vtx::MeshFlags::IS_EYES) || self.mesh.eyeball().is_some()
+    }
+
+    /// The eyeball rendered by this mesh, if the mesh is an eye
+    pub fn eyeball(&self) -> Option<&'a mdl::Eyeball> {
+        self.mesh.mdl().body_parts[self.body_part].models[self.model]
+            .eyeballs
+            .get(self.mesh.eyeball()?)
+    }
+
+    /// Vertex indices for each triangle of the mesh
+    pub fn triangles(&self) -> impl Iterator<Item = [usize; 3]> + 'a {
+        self.vertex_strip_indices()
+            .flat_map(|strip| strip.tuples().map(|(a, b, c)| [a, b, c]))
     }
 }
 
@@ -95,6 +475,23 @@ fn read_indexes<I: Iterator<Item = usize> + 'static, T: Readable>(
         .map(|data| data.and_then(|data| T::read(data)))
 }
 
+fn read_string(data: &[u8], offset: i32) -> Result<String, ModelError> {
+    let data = usize::try_from(offset)
+        .ok()
+        .and_then(|offset| data.get(offset..))
+        .ok_or(ModelError::OutOfBounds {
+            data: "string",
+            offset: offset as usize,
+        })?;
+    let end = data
+        .iter()
+        .position(|c| *c == 0)
+        .ok_or(StringError::NotNullTerminated)?;
+    Ok(std::str::from_utf8(&data[..end])
+        .map_err(StringError::NonUTF8)?
+        .to_string())
+}
+
 fn index_range(index: i32, count: i32, size: usize) -> impl Iterator<Item = usize> {
     (0..count as usize)
         .map(move |i| i * size)
@@ -0,0 +1,524 @@
+use crate::ani::Ani;
+use crate::mdl::raw::animation::{
+    AnimationBlock, AnimationDescriptionHeader, AnimationFlags, AnimationSectionHeader,
+    AnimationValue, AnimationValuePointer, BoneAnimationFlags, BoneAnimationHeader, Movement,
+    Quaternion48, Quaternion64, Vector48,
+};
+use crate::mdl::{Bone, BoneFlags};
+use crate::{
+    read_relative, read_string, ModelError, Quaternion, RadianEuler, ReadRelative, Readable, Vector,
+};
+use bytemuck::Zeroable;
+use cgmath::{InnerSpace, Matrix4, Vector3};
+use std::mem::size_of;
+
+type Result<T> = std::result::Result<T, ModelError>;
+
+/// Bone number marking the end of the per-bone animation data
+const NO_BONE: u8 = 255;
+
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub name: String,
+    pub fps: f32,
+    pub flags: AnimationFlags,
+    pub frame_count: i32,
+    pub movements: Vec<Movement>,
+    /// Number of frames in each section, 0 if the animation isn't split in sections
+    pub section_frames: i32,
+    /// The animation data, either as a single section or split in sections of `section_frames` frames
+    pub sections: Vec<AnimationSection>,
+}
+
+impl ReadRelative for Animation {
+    type Header = AnimationDescriptionHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self> {
+        let section_headers: Vec<AnimationSectionHeader> =
+            read_relative(data, header.section_indexes())?;
+        let frame_count = header.frame_count.max(1) as usize;
+        let sections = if section_headers.is_empty() {
+            vec![AnimationSection::read(
+                data,
+                header.animation_block,
+                header.animation_index,
+                frame_count,
+            )?]
+        } else {
+            let section_frames = header.section_frames.max(1) as usize;
+            let last_section = frame_count / section_frames + 1;
+            section_headers
+                .into_iter()
+                .enumerate()
+                .map(|(index, section)| {
+                    // sections contain one extra frame to interpolate into the next section,
+                    // except for the section holding the last frame of the animation
+                    let frames = if index == last_section {
+                        1
+                    } else {
+                        (section_frames + 1).min(frame_count.saturating_sub(index * section_frames))
+                    };
+                    AnimationSection::read(
+                        data,
+                        section.animation_block,
+                        section.animation_index,
+                        frames,
+                    )
+                })
+                .collect::<Result<_>>()?
+        };
+        Ok(Animation {
+            name: read_string(data, header.name_index)?,
+            fps: header.fps,
+            flags: header.flags,
+            frame_count: header.frame_count,
+            movements: read_relative(data, header.movement_indexes())?,
+            section_frames: header.section_frames,
+            sections,
+        })
+    }
+}
+
+impl Animation {
+    /// Read the animation data stored in animation blocks from the ani file of the model
+    pub fn load_blocks(&mut self, blocks: &[AnimationBlock], ani: &Ani) -> Result<()> {
+        self.sections
+            .iter_mut()
+            .try_for_each(|section| section.load(blocks, ani))
+    }
+
+    /// Whether the animation data for every section is available
+    pub fn is_loaded(&self) -> bool {
+        self.sections.iter().all(|section| section.bones.is_some())
+    }
+
+    /// Get the local transforms of every bone at a frame of the animation
+    ///
+    /// Fractional frames are interpolated, bones that aren't animated keep their bind pose
+    pub fn pose(&self, bones: &[Bone], frame: f32) -> Result<Vec<BonePose>> {
+        let (animated, frame, s) = self.locate(frame)?;
+        let mut poses: Vec<BonePose> = bones.iter().map(|bone| self.rest_pose(bone)).collect();
+        for bone_animation in animated {
+            if let Some(bone) = bones.get(bone_animation.bone as usize) {
+                poses[bone_animation.bone as usize] = bone_animation.pose(bone, frame, s);
+            }
+        }
+        Ok(poses)
+    }
+
+    /// Get the local transform of a single bone at a frame of the animation
+    pub fn bone_pose(&self, bone_index: usize, bone: &Bone, frame: f32) -> Result<BonePose> {
+        let (animated, frame, s) = self.locate(frame)?;
+        Ok(animated
+            .iter()
+            .find(|bone_animation| bone_animation.bone as usize == bone_index)
+            .map(|bone_animation| bone_animation.pose(bone, frame, s))
+            .unwrap_or_else(|| self.rest_pose(bone)))
+    }
+
+    fn rest_pose(&self, bone: &Bone) -> BonePose {
+        if self.flags.contains(AnimationFlags::DELTA) {
+            BonePose::identity()
+        } else {
+            BonePose::from(bone)
+        }
+    }
+
+    /// Find the animation data for a frame, returning the frame within the section and the interpolation factor
+    fn locate(&self, frame: f32) -> Result<(&[BoneAnimation], usize, f32)> {
+        let frame_count = self.frame_count.max(1) as usize;
+        let frame = frame.clamp(0.0, (frame_count - 1) as f32);
+        let whole_frame = frame.floor() as usize;
+        let s = frame - whole_frame as f32;
+
+        let section_frames = self.section_frames.max(0) as usize;
+        let (section, local_frame) = if section_frames == 0 {
+            (0, whole_frame)
+        } else if frame_count > section_frames && whole_frame == frame_count - 1 {
+            // the last frame of long animations is stored separately
+            (frame_count / section_frames + 1, 0)
+        } else {
+            (whole_frame / section_frames, whole_frame % section_frames)
+        };
+        let section = self.sections.get(section).ok_or(ModelError::OutOfBounds {
+            data: "AnimationSection",
+            offset: section,
+        })?;
+        let bones = section
+            .bones
+            .as_deref()
+            .ok_or(ModelError::AnimationBlockNotLoaded(section.block))?;
+        Ok((bones, local_frame, s))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationSection {
+    /// Animation block containing the data, 0 if the data is stored in the mdl file
+    pub block: i32,
+    /// Offset of the data, relative to the animation description for data in the mdl file or
+    /// to the start of the animation block otherwise
+    pub index: i32,
+    /// The animation data for each animated bone
+    ///
+    /// `None` if the data is stored in an animation block that hasn't been loaded
+    pub bones: Option<Vec<BoneAnimation>>,
+    /// Number of frames stored in the section
+    frames: usize,
+}
+
+impl AnimationSection {
+    fn read(data: &[u8], block: i32, index: i32, frames: usize) -> Result<Self> {
+        let bones = match (block, index) {
+            (0, 0) => Some(Vec::new()),
+            (0, _) => Some(BoneAnimation::read_all(data, index, frames)?),
+            _ => None,
+        };
+        Ok(AnimationSection {
+            block,
+            index,
+            bones,
+            frames,
+        })
+    }
+
+    /// Read the animation data from the animation blocks if it isn't loaded yet
+    fn load(&mut self, blocks: &[AnimationBlock], ani: &Ani) -> Result<()> {
+        if self.bones.is_some() {
+            return Ok(());
+        }
+        let block = usize::try_from(self.block)
+            .ok()
+            .and_then(|block| blocks.get(block))
+            .ok_or(ModelError::OutOfBounds {
+                data: "AnimationBlock",
+                offset: self.block as usize,
+            })?;
+        self.bones = Some(BoneAnimation::read_all(
+            ani.block(block)?,
+            self.index,
+            self.frames,
+        )?);
+        Ok(())
+    }
+}
+
+/// The animation data for a single bone
+#[derive(Debug, Clone)]
+pub struct BoneAnimation {
+    pub bone: u8,
+    pub flags: BoneAnimationFlags,
+    /// Offset of the bone data, relative to the same base as [`AnimationSection::index`]
+    pub offset: usize,
+    pub rotation: RotationTrack,
+    pub position: PositionTrack,
+}
+
+impl BoneAnimation {
+    /// Read the linked list of bone animations starting at `index`
+    fn read_all(data: &[u8], index: i32, frames: usize) -> Result<Vec<Self>> {
+        let mut bones = Vec::new();
+        let mut offset = usize::try_from(index).map_err(|_| ModelError::OutOfBounds {
+            data: "BoneAnimation",
+            offset: index as usize,
+        })?;
+        // there can be at most one entry per bone
+        while bones.len() < NO_BONE as usize {
+            let header = <BoneAnimationHeader as Readable>::read(data.get(offset..).ok_or(
+                ModelError::OutOfBounds {
+                    data: "BoneAnimation",
+                    offset,
+                },
+            )?)?;
+            if header.bone == NO_BONE {
+                break;
+            }
+            bones.push(BoneAnimation::read(data, offset, header, frames)?);
+            if header.next_offset <= 0 {
+                break;
+            }
+            offset += header.next_offset as usize;
+        }
+        Ok(bones)
+    }
+
+    fn read(
+        data: &[u8],
+        offset: usize,
+        header: BoneAnimationHeader,
+        frames: usize,
+    ) -> Result<Self> {
+        let flags = header.flags;
+        let data_offset = offset + size_of::<BoneAnimationHeader>();
+        let read_at = |offset: usize| {
+            data.get(offset..).ok_or(ModelError::OutOfBounds {
+                data: "BoneAnimation",
+                offset,
+            })
+        };
+
+        let rotation = if flags.contains(BoneAnimationFlags::RAW_ROT) {
+            RotationTrack::Fixed(<Quaternion48 as Readable>::read(read_at(data_offset)?)?.into())
+        } else if flags.contains(BoneAnimationFlags::RAW_ROT2) {
+            RotationTrack::Fixed(<Quaternion64 as Readable>::read(read_at(data_offset)?)?.into())
+        } else if flags.contains(BoneAnimationFlags::ANIM_ROT) {
+            RotationTrack::Animated(AnimationTrack::read(data, data_offset, frames)?)
+        } else {
+            RotationTrack::None
+        };
+
+        let position = if flags.contains(BoneAnimationFlags::RAW_POS) {
+            let mut position_offset = data_offset;
+            if flags.contains(BoneAnimationFlags::RAW_ROT) {
+                position_offset += size_of::<Quaternion48>();
+            }
+            if flags.contains(BoneAnimationFlags::RAW_ROT2) {
+                position_offset += size_of::<Quaternion64>();
+            }
+            PositionTrack::Fixed(<Vector48 as Readable>::read(read_at(position_offset)?)?.into())
+        } else if flags.contains(BoneAnimationFlags::ANIM_POS) {
+            let mut position_offset = data_offset;
+            if flags.contains(BoneAnimationFlags::ANIM_ROT) {
+                position_offset += size_of::<AnimationValuePointer>();
+            }
+            PositionTrack::Animated(AnimationTrack::read(data, position_offset, frames)?)
+        } else {
+            PositionTrack::None
+        };
+
+        Ok(BoneAnimation {
+            bone: header.bone,
+            flags,
+            offset,
+            rotation,
+            position,
+        })
+    }
+
+    /// Get the local transform of the bone, interpolating `s` of the way to the next frame
+    pub fn pose(&self, bone: &Bone, frame: usize, s: f32) -> BonePose {
+        let delta = self.flags.contains(BoneAnimationFlags::DELTA);
+        BonePose {
+            position: self.position(bone, frame, s, delta),
+            rotation: self.rotation(bone, frame, s, delta),
+        }
+    }
+
+    fn rotation(&self, bone: &Bone, frame: usize, s: f32, delta: bool) -> cgmath::Quaternion<f32> {
+        let track = match &self.rotation {
+            RotationTrack::Fixed(rotation) => return (*rotation).into(),
+            RotationTrack::None if delta => return BonePose::identity().rotation,
+            RotationTrack::None => return bone.quaternion.into(),
+            RotationTrack::Animated(track) => track,
+        };
+
+        let to_quaternion = |angles: [f32; 3]| {
+            let base = if delta {
+                RadianEuler::zeroed()
+            } else {
+                bone.rot
+            };
+            cgmath::Quaternion::from(RadianEuler {
+                x: angles[0] + base.x,
+                y: angles[1] + base.y,
+                z: angles[2] + base.z,
+            })
+        };
+        let (current, next) = track.values(frame, bone.rot_scale);
+        let rotation = if s > 0.001 {
+            to_quaternion(current).nlerp(to_quaternion(next), s)
+        } else {
+            to_quaternion(current)
+        };
+
+        if !delta && bone.flags.contains(BoneFlags::BONE_FIXED_ALIGNMENT) {
+            let alignment = cgmath::Quaternion::from(bone.q_alignment);
+            if rotation.dot(alignment) < 0.0 {
+                return -rotation;
+            }
+        }
+        rotation
+    }
+
+    fn position(&self, bone: &Bone, frame: usize, s: f32, delta: bool) -> Vector3<f32> {
+        let track = match &self.position {
+            PositionTrack::Fixed(position) => return (*position).into(),
+            PositionTrack::None if delta => return BonePose::identity().position,
+            PositionTrack::None => return bone.pos.into(),
+            PositionTrack::Animated(track) => track,
+        };
+
+        let (current, next) = track.values(frame, bone.pos_scale);
+        let position = Vector3::from(current) * (1.0 - s) + Vector3::from(next) * s;
+        if delta {
+            position
+        } else {
+            position + Vector3::from(bone.pos)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum RotationTrack {
+    /// The rotation isn't animated, the bind pose is used or no rotation for delta animations
+    None,
+    /// A single rotation used for the whole animation
+    Fixed(Quaternion),
+    /// Euler angles for each frame, scaled by the rotation scale of the bone
+    Animated(AnimationTrack),
+}
+
+#[derive(Debug, Clone)]
+pub enum PositionTrack {
+    /// The position isn't animated, the bind pose is used or no offset for delta animations
+    None,
+    /// A single position used for the whole animation
+    Fixed(Vector),
+    /// Positions for each frame, scaled by the position scale of the bone
+    Animated(AnimationTrack),
+}
+
+/// Run length encoded values for each axis of an animated bone
+#[derive(Debug, Clone, Default)]
+pub struct AnimationTrack {
+    axes: [Vec<AnimationValue>; 3],
+}
+
+impl AnimationTrack {
+    fn read(data: &[u8], offset: usize, frames: usize) -> Result<Self> {
+        let pointer = <AnimationValuePointer as Readable>::read(data.get(offset..).ok_or(
+            ModelError::OutOfBounds {
+                data: "AnimationValuePointer",
+                offset,
+            },
+        )?)?;
+        let mut axes: [Vec<AnimationValue>; 3] = Default::default();
+        for (values, axis_offset) in axes.iter_mut().zip(pointer.offset) {
+            if axis_offset > 0 {
+                *values = read_values(data, offset + axis_offset as usize, frames)?;
+            }
+        }
+        Ok(AnimationTrack { axes })
+    }
+
+    /// Get the values for each axis at a frame and the frame after it
+    pub fn values(&self, frame: usize, scale: Vector) -> ([f32; 3], [f32; 3]) {
+        let mut current = [0.0; 3];
+        let mut next = [0.0; 3];
+        for (axis, scale) in scale.iter().enumerate() {
+            (current[axis], next[axis]) = extract_value(&self.axes[axis], frame, scale);
+        }
+        (current, next)
+    }
+}
+
+/// Read the runs of animation values needed to cover `frames` frames
+fn read_values(data: &[u8], offset: usize, frames: usize) -> Result<Vec<AnimationValue>> {
+    let mut values = Vec::new();
+    let mut covered = 0;
+    let mut offset = offset;
+    // read one run past the required frames if available, for interpolating into the next section
+    while covered <= frames {
+        let run = data
+            .get(offset..offset + size_of::<AnimationValue>())
+            .map(<AnimationValue as Readable>::read)
+            .transpose()?
+            .and_then(|header| {
+                let len = (header.valid() as usize + 1) * size_of::<AnimationValue>();
+                Some((header, data.get(offset..offset + len)?))
+            });
+        let Some((header, run)) = run else {
+            if covered < frames {
+                return Err(ModelError::OutOfBounds {
+                    data: "AnimationValue",
+                    offset,
+                });
+            }
+            break;
+        };
+        if header.total() == 0 {
+            break;
+        }
+        values.extend(
+            run.chunks_exact(size_of::<AnimationValue>())
+                .map(|value| AnimationValue(i16::from_le_bytes([value[0], value[1]]))),
+        );
+        covered += header.total() as usize;
+        offset += run.len();
+    }
+    Ok(values)
+}
+
+/// Get the value at a frame and the frame after it from run length encoded values
+fn extract_value(values: &[AnimationValue], frame: usize, scale: f32) -> (f32, f32) {
+    let mut k = frame;
+    let mut run = 0;
+    let (valid, total) = loop {
+        let Some(header) = values.get(run) else {
+            return (0.0, 0.0);
+        };
+        let (valid, total) = (header.valid() as usize, header.total() as usize);
+        if total == 0 {
+            return (0.0, 0.0);
+        }
+        if total > k {
+            break (valid, total);
+        }
+        k -= total;
+        run += valid + 1;
+    };
+
+    let get = |index: usize| {
+        values
+            .get(run + index)
+            .map(|value| value.value() as f32 * scale)
+    };
+    let current = if valid > k { get(k + 1) } else { get(valid) }.unwrap_or_default();
+    let next = if valid > k + 1 {
+        get(k + 2)
+    } else if total > k + 1 {
+        Some(current)
+    } else {
+        // first value of the next run
+        get(valid + 2)
+    };
+    (current, next.unwrap_or(current))
+}
+
+/// Transform of a bone relative to its parent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BonePose {
+    pub position: Vector3<f32>,
+    pub rotation: cgmath::Quaternion<f32>,
+}
+
+impl BonePose {
+    pub fn identity() -> Self {
+        BonePose {
+            position: Vector3::new(0.0, 0.0, 0.0),
+            rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Interpolate `t` of the way towards another pose
+    pub fn blend(&self, other: &BonePose, t: f32) -> BonePose {
+        BonePose {
+            position: self.position * (1.0 - t) + other.position * t,
+            rotation: self.rotation.slerp(other.rotation, t),
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.position) * Matrix4::from(self.rotation)
+    }
+}
+
+impl From<&Bone> for BonePose {
+    /// The bind pose of the bone
+    fn from(bone: &Bone) -> Self {
+        BonePose {
+            position: bone.pos.into(),
+            rotation: bone.quaternion.into(),
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use crate::mdl::raw::AttachmentHeader;
+use crate::{read_string, Matrix3x4, ModelError, ReadRelative};
+use cgmath::Matrix4;
+
+/// A named point attached to a bone, used to place effects and other models
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub name: String,
+    pub flags: u32,
+    pub bone: i32,
+    /// Transform of the attachment relative to the bone
+    pub local: Matrix3x4,
+}
+
+impl ReadRelative for Attachment {
+    type Header = AttachmentHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self, ModelError> {
+        Ok(Attachment {
+            name: read_string(data, header.name_index)?,
+            flags: header.flags,
+            bone: header.local_bone,
+            local: header.local,
+        })
+    }
+}
+
+impl Attachment {
+    /// Get the world transform of the attachment from the world transforms of the bones
+    pub fn world_transform(&self, bones: &[Matrix4<f32>]) -> Option<Matrix4<f32>> {
+        let bone = bones.get(usize::try_from(self.bone).ok()?)?;
+        Some(bone * Matrix4::from(self.local))
+    }
+}
@@ -0,0 +1,32 @@
+use crate::mdl::raw::{BoneControllerFlags, BoneControllerHeader};
+use crate::{ModelError, ReadRelative};
+
+/// A controller moving or rotating a bone along one axis, set by the game
+#[derive(Debug, Clone)]
+pub struct BoneController {
+    /// The bone moved by the controller, -1 if the controller isn't attached to a bone
+    pub bone: i32,
+    /// The axis of the bone that is controlled
+    pub ty: BoneControllerFlags,
+    pub start: f32,
+    pub end: f32,
+    /// Value of the controller at rest, as a byte value between `start` and `end`
+    pub rest: i32,
+    /// Input driving the controller, 0-3 are set by the game and 4 is the mouth
+    pub input_field: i32,
+}
+
+impl ReadRelative for BoneController {
+    type Header = BoneControllerHeader;
+
+    fn read(_data: &[u8], header: Self::Header) -> Result<Self, ModelError> {
+        Ok(BoneController {
+            bone: header.bone,
+            ty: header.ty,
+            start: header.start,
+            end: header.end,
+            rest: header.rest,
+            input_field: header.input_field,
+        })
+    }
+}
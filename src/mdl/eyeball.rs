@@ -0,0 +1,65 @@
+use crate::mdl::raw::EyeballHeader;
+use crate::{read_string, ModelError, ReadRelative, Vector};
+use cgmath::{Matrix4, Point3, Transform};
+
+/// An eye of a model, rendered by the eye shader and aimed at a target by the engine
+#[derive(Debug, Clone)]
+pub struct Eyeball {
+    pub name: String,
+    pub bone: i32,
+    /// Center of the eyeball relative to the bone
+    pub origin: Vector,
+    pub z_offset: f32,
+    pub radius: f32,
+    pub up: Vector,
+    pub forward: Vector,
+    /// Skin reference of the eye material
+    pub texture: i32,
+    pub iris_scale: f32,
+    /// Flex descriptors for the raised, neutral and lowered positions of the upper eyelid
+    pub upper_flex_descriptors: [i32; 3],
+    /// Flex descriptors for the raised, neutral and lowered positions of the lower eyelid
+    pub lower_flex_descriptors: [i32; 3],
+    /// Angle in radians of the raised, neutral and lowered positions of the upper eyelid
+    pub upper_targets: [f32; 3],
+    /// Angle in radians of the raised, neutral and lowered positions of the lower eyelid
+    pub lower_targets: [f32; 3],
+    /// Flex descriptor controlling the upper eyelid
+    pub upper_lid_flex_descriptor: i32,
+    /// Flex descriptor controlling the lower eyelid
+    pub lower_lid_flex_descriptor: i32,
+    pub non_facs: bool,
+}
+
+impl ReadRelative for Eyeball {
+    type Header = EyeballHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self, ModelError> {
+        Ok(Eyeball {
+            name: read_string(data, header.name_index)?,
+            bone: header.bone,
+            origin: header.origin,
+            z_offset: header.z_offset,
+            radius: header.radius,
+            up: header.up,
+            forward: header.forward,
+            texture: header.texture,
+            iris_scale: header.iris_scale,
+            upper_flex_descriptors: header.upper_flex_descriptor,
+            lower_flex_descriptors: header.lower_flex_descriptor,
+            upper_targets: header.upper_target,
+            lower_targets: header.lower_target,
+            upper_lid_flex_descriptor: header.upper_lid_flex_descriptor,
+            lower_lid_flex_descriptor: header.lower_lid_flex_descriptor,
+            non_facs: header.non_facs != 0,
+        })
+    }
+}
+
+impl Eyeball {
+    /// Get the center of the eyeball from the world transforms of the bones
+    pub fn world_origin(&self, bones: &[Matrix4<f32>]) -> Option<Point3<f32>> {
+        let bone = bones.get(usize::try_from(self.bone).ok()?)?;
+        Some(bone.transform_point(Point3::new(self.origin.x, self.origin.y, self.origin.z)))
+    }
+}
@@ -0,0 +1,494 @@
+use crate::mdl::raw::animation::f16_to_f32;
+use crate::mdl::raw::flex::{
+    FlexControllerHeader, FlexControllerUiHeader, FlexDescriptorHeader, FlexHeader, FlexOpHeader,
+    FlexRuleHeader, VertexAnimationHeader, VERTEX_ANIMATION_WRINKLE,
+};
+use crate::mdl::raw::MouthHeader;
+use crate::mdl::StudioHeader;
+use crate::{read_indexes, read_string, ModelError, ReadRelative, Readable, Vector};
+use bytemuck::Zeroable;
+use std::mem::size_of;
+
+type Result<T> = std::result::Result<T, ModelError>;
+
+/// A flex, a morph target driven by the flex rules
+#[derive(Debug, Clone)]
+pub struct FlexDescriptor {
+    pub name: String,
+}
+
+impl ReadRelative for FlexDescriptor {
+    type Header = FlexDescriptorHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self> {
+        Ok(FlexDescriptor {
+            name: read_string(data, header.name_index)?,
+        })
+    }
+}
+
+/// An input of the flex rules, set by the game or by facial animations
+#[derive(Debug, Clone)]
+pub struct FlexController {
+    /// Category of the controller, such as "eyes" or "phoneme"
+    pub ty: String,
+    pub name: String,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl ReadRelative for FlexController {
+    type Header = FlexControllerHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self> {
+        Ok(FlexController {
+            ty: read_string(data, header.type_index)?,
+            name: read_string(data, header.name_index)?,
+            min: header.min,
+            max: header.max,
+        })
+    }
+}
+
+/// An expression calculating the weight of a flex from the flex controllers
+#[derive(Debug, Clone)]
+pub struct FlexRule {
+    /// The flex set by the rule
+    pub flex: i32,
+    /// The operations of the expression in reverse polish notation
+    pub ops: Vec<FlexOp>,
+}
+
+impl ReadRelative for FlexRule {
+    type Header = FlexRuleHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self> {
+        Ok(FlexRule {
+            flex: header.flex,
+            ops: read_indexes::<_, FlexOpHeader>(header.op_indexes(), data)
+                .map(|op| op.map(FlexOp::from))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl FlexRule {
+    /// Calculate the weight of the flex from the values of the flex controllers and the weights of
+    /// the flexes calculated by the previous rules
+    pub fn evaluate(&self, controllers: &[FlexController], values: &[f32], weights: &[f32]) -> f32 {
+        let value = |index: usize| values.get(index).copied().unwrap_or_default();
+        // value of a controller remapped from its range to the specified range
+        let remapped = |index: f32, min: f32, max: f32| {
+            let Some(controller) = (index >= 0.0)
+                .then(|| controllers.get(index as usize))
+                .flatten()
+            else {
+                return 0.0;
+            };
+            remap_clamped(
+                value(index as usize),
+                controller.min,
+                controller.max,
+                min,
+                max,
+            )
+        };
+
+        let mut stack: Vec<f32> = Vec::new();
+        let pop = |stack: &mut Vec<f32>| stack.pop().unwrap_or_default();
+        for op in &self.ops {
+            match *op {
+                FlexOp::Const(constant) => stack.push(constant),
+                FlexOp::Fetch1(controller) => stack.push(value(controller)),
+                FlexOp::Fetch2(flex) => stack.push(weights.get(flex).copied().unwrap_or_default()),
+                FlexOp::Add
+                | FlexOp::Sub
+                | FlexOp::Mul
+                | FlexOp::Div
+                | FlexOp::Max
+                | FlexOp::Min => {
+                    let b = pop(&mut stack);
+                    let a = pop(&mut stack);
+                    stack.push(match op {
+                        FlexOp::Add => a + b,
+                        FlexOp::Sub => a - b,
+                        FlexOp::Mul => a * b,
+                        FlexOp::Div if b > 0.0001 => a / b,
+                        FlexOp::Div => 0.0,
+                        FlexOp::Max => a.max(b),
+                        _ => a.min(b),
+                    });
+                }
+                FlexOp::Neg => {
+                    let a = pop(&mut stack);
+                    stack.push(-a);
+                }
+                FlexOp::Exp => {
+                    let a = pop(&mut stack);
+                    stack.push(a.exp());
+                }
+                FlexOp::Open | FlexOp::Close | FlexOp::Comma | FlexOp::Other { .. } => {}
+                FlexOp::TwoWay0(controller) => {
+                    stack.push(remap_clamped(value(controller), -1.0, 0.0, 1.0, 0.0))
+                }
+                FlexOp::TwoWay1(controller) => {
+                    stack.push(remap_clamped(value(controller), 0.0, 1.0, 0.0, 1.0))
+                }
+                FlexOp::NWay(controller) => {
+                    let value_controller = pop(&mut stack);
+                    let mut ramp = [0.0; 4];
+                    for target in ramp.iter_mut().rev() {
+                        *target = pop(&mut stack);
+                    }
+                    let weight = ramp_weight(ramp, value(value_controller.max(0.0) as usize));
+                    stack.push(weight * value(controller));
+                }
+                FlexOp::Combo(count) => {
+                    let values = stack.split_off(stack.len().saturating_sub(count));
+                    stack.push(values.into_iter().product());
+                }
+                FlexOp::Dominate(count) => {
+                    let dominators: f32 = stack
+                        .split_off(stack.len().saturating_sub(count))
+                        .into_iter()
+                        .product();
+                    let a = pop(&mut stack);
+                    stack.push(a * (1.0 - dominators));
+                }
+                FlexOp::DmeLowerEyelid(close_lid_v) | FlexOp::DmeUpperEyelid(close_lid_v) => {
+                    let close_lid = remapped(pop(&mut stack), 0.0, 1.0);
+                    // the blink controller doesn't affect the eyelids
+                    pop(&mut stack);
+                    let eye_up_down = remapped(pop(&mut stack), -1.0, 1.0);
+                    let close_lid_v = remapped(close_lid_v as f32, 0.0, 1.0);
+                    stack.push(match op {
+                        FlexOp::DmeLowerEyelid(_) if eye_up_down > 0.0 => {
+                            (1.0 - eye_up_down) * (1.0 - close_lid_v) * close_lid
+                        }
+                        FlexOp::DmeLowerEyelid(_) => (1.0 - close_lid_v) * close_lid,
+                        _ if eye_up_down < 0.0 => (1.0 + eye_up_down) * close_lid_v * close_lid,
+                        _ => close_lid_v * close_lid,
+                    });
+                }
+            }
+        }
+        stack.first().copied().unwrap_or_default()
+    }
+}
+
+/// Calculate the weight of every flex by running the flex rules on the values of the flex controllers
+///
+/// Flexes without a rule have a weight of 0
+pub fn evaluate_flex_rules(
+    rules: &[FlexRule],
+    controllers: &[FlexController],
+    values: &[f32],
+    flex_count: usize,
+) -> Vec<f32> {
+    let mut weights = vec![0.0; flex_count];
+    for rule in rules {
+        let weight = rule.evaluate(controllers, values, &weights);
+        if let Some(flex) = usize::try_from(rule.flex)
+            .ok()
+            .and_then(|flex| weights.get_mut(flex))
+        {
+            *flex = weight;
+        }
+    }
+    weights
+}
+
+fn remap_clamped(value: f32, a: f32, b: f32, c: f32, d: f32) -> f32 {
+    if a == b {
+        return if value >= b { d } else { c };
+    }
+    let t = ((value - a) / (b - a)).clamp(0.0, 1.0);
+    c + (d - c) * t
+}
+
+/// Map a weight onto a ramp that rises from `ramp[0]` to `ramp[1]` and falls from `ramp[2]` to `ramp[3]`
+fn ramp_weight(ramp: [f32; 4], weight: f32) -> f32 {
+    if weight <= ramp[0] || weight >= ramp[3] {
+        0.0
+    } else if weight < ramp[1] {
+        (weight - ramp[0]) / (ramp[1] - ramp[0])
+    } else if weight > ramp[2] {
+        (ramp[3] - weight) / (ramp[3] - ramp[2])
+    } else {
+        1.0
+    }
+}
+
+/// A single operation of a flex rule
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlexOp {
+    /// Push a constant value
+    Const(f32),
+    /// Push the value of a flex controller
+    Fetch1(usize),
+    /// Push the weight of a previously calculated flex
+    Fetch2(usize),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+    Exp,
+    Open,
+    Close,
+    Comma,
+    Max,
+    Min,
+    /// Push the negative side of a two way flex controller
+    TwoWay0(usize),
+    /// Push the positive side of a two way flex controller
+    TwoWay1(usize),
+    /// Push the value of the controller weighted by the ramp and the value controller popped from the stack
+    NWay(usize),
+    /// Multiply the specified number of values from the stack
+    Combo(usize),
+    /// Multiply the first value by one minus the product of the specified number of values from the stack
+    Dominate(usize),
+    /// Push the weight of the lower eyelid from the eyelid controllers on the stack
+    DmeLowerEyelid(usize),
+    /// Push the weight of the upper eyelid from the eyelid controllers on the stack
+    DmeUpperEyelid(usize),
+    /// An operation not known by this parser
+    Other {
+        op: i32,
+        data: i32,
+    },
+}
+
+impl From<FlexOpHeader> for FlexOp {
+    fn from(header: FlexOpHeader) -> Self {
+        let index = header.data.max(0) as usize;
+        match header.op {
+            1 => FlexOp::Const(f32::from_bits(header.data as u32)),
+            2 => FlexOp::Fetch1(index),
+            3 => FlexOp::Fetch2(index),
+            4 => FlexOp::Add,
+            5 => FlexOp::Sub,
+            6 => FlexOp::Mul,
+            7 => FlexOp::Div,
+            8 => FlexOp::Neg,
+            9 => FlexOp::Exp,
+            10 => FlexOp::Open,
+            11 => FlexOp::Close,
+            12 => FlexOp::Comma,
+            13 => FlexOp::Max,
+            14 => FlexOp::Min,
+            15 => FlexOp::TwoWay0(index),
+            16 => FlexOp::TwoWay1(index),
+            17 => FlexOp::NWay(index),
+            18 => FlexOp::Combo(index),
+            19 => FlexOp::Dominate(index),
+            20 => FlexOp::DmeLowerEyelid(index),
+            21 => FlexOp::DmeUpperEyelid(index),
+            op => FlexOp::Other {
+                op,
+                data: header.data,
+            },
+        }
+    }
+}
+
+/// How the slider of a flex controller ui maps to the flex controllers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlexRemapType {
+    /// The value is passed to the controller directly
+    PassThru,
+    /// The value is split in a negative and positive controller
+    TwoWay,
+    /// The value is mapped over multiple controllers, using an extra value controller
+    NWay,
+    /// The value controls the eyelid
+    Eyelid,
+    Other(u8),
+}
+
+impl From<u8> for FlexRemapType {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => FlexRemapType::PassThru,
+            1 => FlexRemapType::TwoWay,
+            2 => FlexRemapType::NWay,
+            3 => FlexRemapType::Eyelid,
+            other => FlexRemapType::Other(other),
+        }
+    }
+}
+
+/// A slider controlling one or more flex controllers, as shown in the model viewer
+#[derive(Debug, Clone)]
+pub struct FlexControllerUi {
+    pub name: String,
+    pub remap_type: FlexRemapType,
+    /// Stereo controls use separate controllers for the left and right side
+    pub stereo: bool,
+    /// The controller for mono controls, or the left controller for stereo controls
+    pub controller: Option<usize>,
+    /// The right controller for stereo controls
+    pub right_controller: Option<usize>,
+    /// The controller for the value of n-way controls
+    pub value_controller: Option<usize>,
+}
+
+impl FlexControllerUi {
+    /// Read the ui at `offset`, resolving the controller offsets into indexes of the flex controllers
+    pub(crate) fn read(
+        data: &[u8],
+        header: FlexControllerUiHeader,
+        offset: usize,
+        studio_header: &StudioHeader,
+    ) -> Result<Self> {
+        let controllers: Vec<usize> = studio_header.flex_controller_indexes().collect();
+        let controller = |relative: i32| {
+            if relative == 0 {
+                return None;
+            }
+            let absolute = offset.checked_add_signed(relative as isize)?;
+            controllers.iter().position(|index| *index == absolute)
+        };
+        Ok(FlexControllerUi {
+            name: read_string(data, header.name_index)?,
+            remap_type: header.remap_type.into(),
+            stereo: header.stereo != 0,
+            controller: controller(header.controller_index[0]),
+            right_controller: controller(header.controller_index[1]),
+            value_controller: controller(header.controller_index[2]),
+        })
+    }
+}
+
+/// A morph target of a mesh, moving its vertices by the weight of a flex
+#[derive(Debug, Clone)]
+pub struct Flex {
+    pub flex_descriptor: i32,
+    /// Ramp mapping the flex weight to the weight of the morph target
+    pub target: [f32; 4],
+    /// The flex descriptor for the right side of stereo flexes, 0 if the flex isn't stereo
+    pub flex_pair: i32,
+    pub vertices: Vec<FlexVertex>,
+}
+
+impl ReadRelative for Flex {
+    type Header = FlexHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self> {
+        let wrinkle = header.vertex_animation_type == VERTEX_ANIMATION_WRINKLE;
+        let vertices = header
+            .vertex_indexes()
+            .map(|index| {
+                let data = data.get(index..).ok_or(ModelError::OutOfBounds {
+                    data: "FlexVertex",
+                    offset: index,
+                })?;
+                let raw = <VertexAnimationHeader as Readable>::read(data)?;
+                let raw_wrinkle = if wrinkle {
+                    <i16 as Readable>::read(&data[size_of::<VertexAnimationHeader>()..])?
+                } else {
+                    0
+                };
+                let mut vertex = FlexVertex {
+                    index: raw.index as usize,
+                    speed: raw.speed,
+                    side: raw.side,
+                    position: Vector::zeroed(),
+                    normal: Vector::zeroed(),
+                    wrinkle: 0.0,
+                    raw,
+                    raw_wrinkle,
+                };
+                vertex.decode(None);
+                Ok(vertex)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Flex {
+            flex_descriptor: header.flex_descriptor,
+            target: header.target,
+            flex_pair: header.flex_pair,
+            vertices,
+        })
+    }
+}
+
+impl Flex {
+    /// Get the weight of the morph target from the weights of the flexes
+    pub fn weight(&self, flex_weights: &[f32]) -> f32 {
+        self.ramped_weight(self.flex_descriptor, flex_weights)
+    }
+
+    /// Get the weight of the morph target for a vertex, blending between both sides for stereo flexes
+    pub fn vertex_weight(&self, vertex: &FlexVertex, flex_weights: &[f32]) -> f32 {
+        let left = self.weight(flex_weights);
+        if self.flex_pair == 0 {
+            return left;
+        }
+        let right = self.ramped_weight(self.flex_pair, flex_weights);
+        let side = vertex.side as f32 / 255.0;
+        left * (1.0 - side) + right * side
+    }
+
+    fn ramped_weight(&self, flex: i32, flex_weights: &[f32]) -> f32 {
+        usize::try_from(flex)
+            .ok()
+            .and_then(|flex| flex_weights.get(flex))
+            .map(|weight| ramp_weight(self.target, *weight))
+            .unwrap_or_default()
+    }
+}
+
+/// The offsets of a single vertex in a morph target
+#[derive(Debug, Clone)]
+pub struct FlexVertex {
+    /// Index of the vertex within the mesh
+    pub index: usize,
+    pub speed: u8,
+    /// Blend between the left (0) and right (255) side for stereo flexes
+    pub side: u8,
+    pub position: Vector,
+    pub normal: Vector,
+    /// Weight of the wrinkle map, 0 if the flex has no wrinkle data
+    pub wrinkle: f32,
+    raw: VertexAnimationHeader,
+    raw_wrinkle: i16,
+}
+
+impl FlexVertex {
+    /// Decode the deltas as float16 values, or as fixed point values with the specified scale
+    pub(crate) fn decode(&mut self, fixed_point_scale: Option<f32>) {
+        let decode = |value: u16| match fixed_point_scale {
+            Some(scale) => value as i16 as f32 * scale,
+            None => f16_to_f32(value),
+        };
+        let [x, y, z] = self.raw.delta.map(decode);
+        self.position = Vector { x, y, z };
+        let [x, y, z] = self.raw.normal_delta.map(decode);
+        self.normal = Vector { x, y, z };
+        self.wrinkle = match fixed_point_scale {
+            Some(scale) => self.raw_wrinkle as f32 * scale,
+            None => self.raw_wrinkle as f32 / 32767.0,
+        };
+    }
+}
+
+/// A mouth of the model, opened by the flex controllers for lip sync
+#[derive(Debug, Clone)]
+pub struct Mouth {
+    pub bone: i32,
+    pub forward: Vector,
+    pub flex_descriptor: i32,
+}
+
+impl ReadRelative for Mouth {
+    type Header = MouthHeader;
+
+    fn read(_data: &[u8], header: Self::Header) -> Result<Self> {
+        Ok(Mouth {
+            bone: header.bone,
+            forward: header.forward,
+            flex_descriptor: header.flex_descriptor,
+        })
+    }
+}
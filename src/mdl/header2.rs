@@ -0,0 +1,105 @@
+use crate::mdl::raw::header2::{
+    BoneFlexDriverControl, BoneFlexDriverHeader, LinearBoneHeader, SourceBoneTransformHeader,
+};
+use crate::mdl::raw::BoneFlags;
+use crate::{
+    read_indexes, read_string, Matrix3x4, ModelError, Quaternion, RadianEuler, ReadRelative, Vector,
+};
+
+type Result<T> = std::result::Result<T, ModelError>;
+
+/// Transform applied to a bone of a source file when compiling the model
+#[derive(Debug, Clone)]
+pub struct SourceBoneTransform {
+    pub name: String,
+    pub pre_transform: Matrix3x4,
+    pub post_transform: Matrix3x4,
+}
+
+impl ReadRelative for SourceBoneTransform {
+    type Header = SourceBoneTransformHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self> {
+        Ok(SourceBoneTransform {
+            name: read_string(data, header.name_index)?,
+            pre_transform: header.pre_transform,
+            post_transform: header.post_transform,
+        })
+    }
+}
+
+/// Copy of the bone data with each field stored in its own array, indexed by bone
+#[derive(Debug, Clone)]
+pub struct LinearBones {
+    pub flags: Vec<BoneFlags>,
+    pub parents: Vec<i32>,
+    pub positions: Vec<Vector>,
+    pub quaternions: Vec<Quaternion>,
+    pub rotations: Vec<RadianEuler>,
+    pub pose_to_bone: Vec<Matrix3x4>,
+    pub position_scales: Vec<Vector>,
+    pub rotation_scales: Vec<Vector>,
+    pub q_alignments: Vec<Quaternion>,
+}
+
+impl LinearBones {
+    /// Number of bones in the table
+    pub fn len(&self) -> usize {
+        self.parents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parents.is_empty()
+    }
+}
+
+impl ReadRelative for LinearBones {
+    type Header = LinearBoneHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self> {
+        Ok(LinearBones {
+            flags: read_indexes(header.indexes::<BoneFlags>(header.flags_index), data)
+                .collect::<Result<_>>()?,
+            parents: read_indexes(header.indexes::<i32>(header.parent_index), data)
+                .collect::<Result<_>>()?,
+            positions: read_indexes(header.indexes::<Vector>(header.pos_index), data)
+                .collect::<Result<_>>()?,
+            quaternions: read_indexes(header.indexes::<Quaternion>(header.quaternion_index), data)
+                .collect::<Result<_>>()?,
+            rotations: read_indexes(header.indexes::<RadianEuler>(header.rot_index), data)
+                .collect::<Result<_>>()?,
+            pose_to_bone: read_indexes(
+                header.indexes::<Matrix3x4>(header.pose_to_bone_index),
+                data,
+            )
+            .collect::<Result<_>>()?,
+            position_scales: read_indexes(header.indexes::<Vector>(header.pos_scale_index), data)
+                .collect::<Result<_>>()?,
+            rotation_scales: read_indexes(header.indexes::<Vector>(header.rot_scale_index), data)
+                .collect::<Result<_>>()?,
+            q_alignments: read_indexes(
+                header.indexes::<Quaternion>(header.q_alignment_index),
+                data,
+            )
+            .collect::<Result<_>>()?,
+        })
+    }
+}
+
+/// Drives flex controllers from the position of a bone
+#[derive(Debug, Clone)]
+pub struct BoneFlexDriver {
+    pub bone: i32,
+    pub controls: Vec<BoneFlexDriverControl>,
+}
+
+impl ReadRelative for BoneFlexDriver {
+    type Header = BoneFlexDriverHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self> {
+        Ok(BoneFlexDriver {
+            bone: header.bone,
+            controls: read_indexes(header.control_indexes(), data).collect::<Result<_>>()?,
+        })
+    }
+}
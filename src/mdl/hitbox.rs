@@ -0,0 +1,150 @@
+use crate::mdl::raw::{HitboxHeader, HitboxSetHeader};
+use crate::{read_relative, read_string, ModelError, ReadRelative, Vector};
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+
+#[derive(Debug, Clone)]
+pub struct HitboxSet {
+    pub name: String,
+    pub hitboxes: Vec<Hitbox>,
+}
+
+impl ReadRelative for HitboxSet {
+    type Header = HitboxSetHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self, ModelError> {
+        Ok(HitboxSet {
+            name: read_string(data, header.name_index)?,
+            hitboxes: read_relative(data, header.hitbox_indexes())?,
+        })
+    }
+}
+
+impl HitboxSet {
+    /// Find the closest hitbox hit by a ray, using the provided world transform for every bone
+    pub fn intersect_ray(&self, bones: &[Matrix4<f32>], ray: &Ray) -> Option<HitboxHit> {
+        self.hitboxes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, hitbox)| {
+                let distance = hitbox.intersect_ray(hitbox.bone_transform(bones)?, ray)?;
+                Some(HitboxHit {
+                    hitbox: index,
+                    group: hitbox.group,
+                    distance,
+                })
+            })
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+
+    /// Find the first hitbox containing a point, using the provided world transform for every bone
+    pub fn hitbox_at_point(&self, bones: &[Matrix4<f32>], point: Point3<f32>) -> Option<usize> {
+        self.hitboxes.iter().position(|hitbox| {
+            hitbox
+                .bone_transform(bones)
+                .is_some_and(|bone| hitbox.contains_point(bone, point))
+        })
+    }
+}
+
+/// An axis aligned box in the space of the bone it's attached to
+#[derive(Debug, Clone)]
+pub struct Hitbox {
+    /// Name of the hitbox, empty if the hitbox has no name
+    pub name: String,
+    pub bone: i32,
+    /// Hit group used by the game to determine the effect of a hit
+    pub group: i32,
+    pub bb_min: Vector,
+    pub bb_max: Vector,
+}
+
+impl ReadRelative for Hitbox {
+    type Header = HitboxHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self, ModelError> {
+        Ok(Hitbox {
+            name: match header.name_index {
+                0 => String::new(),
+                index => read_string(data, index)?,
+            },
+            bone: header.bone,
+            group: header.group,
+            bb_min: header.bb_min,
+            bb_max: header.bb_max,
+        })
+    }
+}
+
+impl Hitbox {
+    fn bone_transform<'a>(&self, bones: &'a [Matrix4<f32>]) -> Option<&'a Matrix4<f32>> {
+        bones.get(usize::try_from(self.bone).ok()?)
+    }
+
+    /// Get the distance along the ray to the first intersection with the hitbox
+    ///
+    /// `bone` is the world transform of the bone the hitbox is attached to
+    pub fn intersect_ray(&self, bone: &Matrix4<f32>, ray: &Ray) -> Option<f32> {
+        let to_local = bone.invert()?;
+        let origin = to_local.transform_point(ray.origin);
+        let direction = to_local.transform_vector(ray.direction);
+
+        // slab test, the distance along the ray is the same in local and world space
+        let mut near = 0.0f32;
+        let mut far = f32::INFINITY;
+        for ((origin, direction), (min, max)) in [origin.x, origin.y, origin.z]
+            .into_iter()
+            .zip([direction.x, direction.y, direction.z])
+            .zip(self.bb_min.iter().zip(self.bb_max.iter()))
+        {
+            if direction.abs() < f32::EPSILON {
+                if origin < min || origin > max {
+                    return None;
+                }
+            } else {
+                let a = (min - origin) / direction;
+                let b = (max - origin) / direction;
+                near = near.max(a.min(b));
+                far = far.min(a.max(b));
+                if near > far {
+                    return None;
+                }
+            }
+        }
+        Some(near * ray.direction.magnitude())
+    }
+
+    /// Check if a point is inside the hitbox
+    ///
+    /// `bone` is the world transform of the bone the hitbox is attached to
+    pub fn contains_point(&self, bone: &Matrix4<f32>, point: Point3<f32>) -> bool {
+        let Some(to_local) = bone.invert() else {
+            return false;
+        };
+        let point = to_local.transform_point(point);
+        [point.x, point.y, point.z]
+            .into_iter()
+            .zip(self.bb_min.iter().zip(self.bb_max.iter()))
+            .all(|(value, (min, max))| value >= min && value <= max)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Ray { origin, direction }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HitboxHit {
+    /// Index of the hitbox in the hitbox set
+    pub hitbox: usize,
+    pub group: i32,
+    /// Distance along the ray to the hit
+    pub distance: f32,
+}
@@ -0,0 +1,66 @@
+use crate::mdl::raw::{IkChainHeader, IkLinkHeader, IkLockHeader};
+use crate::{read_indexes, read_string, ModelError, ReadRelative, Vector};
+
+/// A chain of bones solved with inverse kinematics, such as a leg from the hip to the foot
+#[derive(Debug, Clone)]
+pub struct IkChain {
+    pub name: String,
+    pub link_type: i32,
+    /// The bones of the chain, from the root of the chain to the end
+    pub links: Vec<IkLink>,
+}
+
+impl ReadRelative for IkChain {
+    type Header = IkChainHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self, ModelError> {
+        Ok(IkChain {
+            name: read_string(data, header.name_index)?,
+            link_type: header.link_type,
+            links: read_indexes::<_, IkLinkHeader>(header.link_indexes(), data)
+                .map(|link| {
+                    link.map(|link| IkLink {
+                        bone: link.bone,
+                        knee_direction: link.knee_direction,
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl IkChain {
+    /// The bone at the end of the chain, which is placed at the ik target
+    pub fn end_bone(&self) -> Option<i32> {
+        self.links.last().map(|link| link.bone)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IkLink {
+    pub bone: i32,
+    /// Ideal direction for the joint to bend in
+    pub knee_direction: Vector,
+}
+
+/// Keeps the end of an ik chain in place while the model is animated
+#[derive(Debug, Clone)]
+pub struct IkLock {
+    pub chain: i32,
+    pub position_weight: f32,
+    pub local_rotation_weight: f32,
+    pub flags: i32,
+}
+
+impl ReadRelative for IkLock {
+    type Header = IkLockHeader;
+
+    fn read(_data: &[u8], header: Self::Header) -> Result<Self, ModelError> {
+        Ok(IkLock {
+            chain: header.chain,
+            position_weight: header.position_weight,
+            local_rotation_weight: header.local_rotation_weight,
+            flags: header.flags,
+        })
+    }
+}
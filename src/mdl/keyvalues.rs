@@ -0,0 +1,167 @@
+use crate::ModelError;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+/// A block of key values, as used in the `$keyvalues` of a model
+///
+/// Keys are not unique and keep the order from the source text
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeyValues {
+    pub entries: Vec<(String, KeyValue)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyValue {
+    String(String),
+    Block(KeyValues),
+}
+
+impl KeyValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            KeyValue::String(value) => Some(value),
+            KeyValue::Block(_) => None,
+        }
+    }
+
+    pub fn as_block(&self) -> Option<&KeyValues> {
+        match self {
+            KeyValue::String(_) => None,
+            KeyValue::Block(block) => Some(block),
+        }
+    }
+}
+
+impl KeyValues {
+    /// Parse key values from text
+    pub fn parse(text: &str) -> Result<Self, ModelError> {
+        let mut tokens = Tokenizer {
+            chars: text.char_indices().peekable(),
+        };
+        KeyValues::parse_block(&mut tokens, false)
+    }
+
+    /// Parse entries until the end of the text, or until the closing brace for nested blocks
+    fn parse_block(tokens: &mut Tokenizer, nested: bool) -> Result<Self, ModelError> {
+        let mut entries = Vec::new();
+        loop {
+            let key = match tokens.next()? {
+                Some((_, Token::String(key))) => key,
+                Some((_, Token::Close)) if nested => return Ok(KeyValues { entries }),
+                None if !nested => return Ok(KeyValues { entries }),
+                Some((offset, Token::Close)) => {
+                    return Err(invalid(offset, "unexpected closing brace"))
+                }
+                Some((offset, Token::Open)) => return Err(invalid(offset, "expected a key")),
+                None => return Err(invalid(tokens.offset(), "unclosed block")),
+            };
+            let value = match tokens.next()? {
+                Some((_, Token::String(value))) => KeyValue::String(value),
+                Some((_, Token::Open)) => KeyValue::Block(KeyValues::parse_block(tokens, true)?),
+                Some((offset, Token::Close)) => return Err(invalid(offset, "expected a value")),
+                None => return Err(invalid(tokens.offset(), "expected a value")),
+            };
+            entries.push((key, value));
+        }
+    }
+
+    /// Get the first value for a key, ignoring case
+    pub fn get(&self, key: &str) -> Option<&KeyValue> {
+        self.entries
+            .iter()
+            .find(|(entry, _)| entry.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    /// Get the first string value for a key, ignoring case
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(KeyValue::as_str)
+    }
+
+    /// Get the first block for a key, ignoring case
+    pub fn get_block(&self, key: &str) -> Option<&KeyValues> {
+        self.get(key).and_then(KeyValue::as_block)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &KeyValue)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+fn invalid(offset: usize, reason: &'static str) -> ModelError {
+    ModelError::InvalidKeyValues { offset, reason }
+}
+
+enum Token {
+    String(String),
+    Open,
+    Close,
+}
+
+struct Tokenizer<'a> {
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl Tokenizer<'_> {
+    fn offset(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map(|(offset, _)| *offset)
+            .unwrap_or_default()
+    }
+
+    fn next(&mut self) -> Result<Option<(usize, Token)>, ModelError> {
+        self.skip_whitespace_and_comments();
+        let Some((offset, c)) = self.chars.next() else {
+            return Ok(None);
+        };
+        let token = match c {
+            '{' => Token::Open,
+            '}' => Token::Close,
+            '"' => {
+                // backslashes aren't escape sequences, as they're used in paths
+                let mut value = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, c)) => value.push(c),
+                        None => return Err(invalid(offset, "unterminated string")),
+                    }
+                }
+                Token::String(value)
+            }
+            c => {
+                let mut value = String::from(c);
+                while let Some((_, c)) = self
+                    .chars
+                    .next_if(|(_, c)| !c.is_whitespace() && !matches!(c, '{' | '}' | '"'))
+                {
+                    value.push(c);
+                }
+                Token::String(value)
+            }
+        };
+        Ok(Some((offset, token)))
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+            let mut lookahead = self.chars.clone();
+            let is_comment = matches!(
+                (lookahead.next(), lookahead.next()),
+                (Some((_, '/')), Some((_, '/')))
+            );
+            if !is_comment {
+                return;
+            }
+            while self.chars.next_if(|(_, c)| *c != '\n').is_some() {}
+        }
+    }
+}
@@ -1,47 +1,417 @@
+mod animation;
+mod attachment;
+mod controller;
+mod eyeball;
+mod flex;
+mod header2;
+mod hitbox;
+mod ik;
+mod keyvalues;
 mod raw;
+mod sequence;
+mod skeleton;
+mod virtual_model;
 
+pub use animation::{
+    Animation, AnimationSection, AnimationTrack, BoneAnimation, BonePose, PositionTrack,
+    RotationTrack,
+};
+pub use attachment::Attachment;
+pub use controller::BoneController;
+pub use eyeball::Eyeball;
+pub use flex::{
+    evaluate_flex_rules, Flex, FlexController, FlexControllerUi, FlexDescriptor, FlexOp,
+    FlexRemapType, FlexRule, FlexVertex, Mouth,
+};
+pub use header2::{BoneFlexDriver, LinearBones, SourceBoneTransform};
+pub use hitbox::{Hitbox, HitboxHit, HitboxSet, Ray};
+pub use ik::{IkChain, IkLink, IkLock};
+pub use keyvalues::{KeyValue, KeyValues};
+pub use raw::animation::{
+    AnimationBlock, AnimationFlags, BoneAnimationFlags, MotionFlags, Movement,
+};
 pub use raw::header::*;
 pub use raw::header2::*;
+pub use raw::sequence::EventFlags;
+pub use raw::{Bone, BoneControllerFlags, BoneFlags};
+pub use sequence::{Event, PoseParameter, Sequence};
+pub use skeleton::{BindPose, Skeleton};
 use std::mem::size_of;
+pub use virtual_model::{IncludeModel, ModelGroup, VirtualItem, VirtualModel};
 
-use crate::mdl::raw::{BodyPartHeader, Bone, MeshHeader, ModelHeader};
+use crate::mdl::raw::flex::FlexControllerUiHeader;
+use crate::mdl::raw::sequence::SequenceDescriptionHeader;
+use crate::mdl::raw::{BodyPartHeader, MeshHeader, ModelHeader, TextureHeader};
 use crate::vvd::Vertex;
-use crate::{read_indexes, read_relative, FixedString, ModelError, ReadRelative, Readable};
+use crate::{
+    read_indexes, read_relative, read_string, Ani, FixedString, Handle, ModelError, ReadRelative,
+    Readable, StringError, Vector,
+};
 
 type Result<T> = std::result::Result<T, ModelError>;
 
 #[derive(Debug, Clone)]
 pub struct Mdl {
     pub header: StudioHeader,
+    pub header2: Option<StudioHHeader2>,
+    /// Full name of the model, the name in the header is limited to 64 bytes
+    pub name: String,
     pub bones: Vec<Bone>,
+    bone_names: Vec<String>,
     pub body_parts: Vec<BodyPart>,
+    pub textures: Vec<Texture>,
+    /// Directories to search for the textures, relative to the `materials` directory
+    pub texture_dirs: Vec<String>,
+    pub skin_table: SkinTable,
+    pub hitbox_sets: Vec<HitboxSet>,
+    pub attachments: Vec<Attachment>,
+    pub animations: Vec<Animation>,
+    /// Name of the ani file containing the animation blocks, empty if the model has no animation blocks
+    pub animation_block_file: String,
+    /// Ranges of the animation blocks in the ani file, the first block is unused
+    pub animation_blocks: Vec<AnimationBlock>,
+    pub sequences: Vec<Sequence>,
+    pub pose_parameters: Vec<PoseParameter>,
+    /// Models included with `$includemodel`, see [`VirtualModel`]
+    pub include_models: Vec<IncludeModel>,
+    pub flex_descriptors: Vec<FlexDescriptor>,
+    pub flex_controllers: Vec<FlexController>,
+    pub flex_rules: Vec<FlexRule>,
+    pub flex_controller_uis: Vec<FlexControllerUi>,
+    pub ik_chains: Vec<IkChain>,
+    /// Ik locks applied to every sequence
+    pub ik_locks: Vec<IkLock>,
+    pub bone_controllers: Vec<BoneController>,
+    pub mouths: Vec<Mouth>,
+    key_values: String,
+    /// Names of the transition nodes, node `n` is at index `n - 1`
+    pub node_names: Vec<String>,
+    node_transitions: Vec<u8>,
+    pub source_bone_transforms: Vec<SourceBoneTransform>,
+    /// Bone data stored as separate arrays, only present in some models
+    pub linear_bones: Option<LinearBones>,
+    pub bone_flex_drivers: Vec<BoneFlexDriver>,
 }
 
 impl Mdl {
     pub fn read(data: &[u8]) -> Result<Self> {
         let header = <StudioHeader as Readable>::read(data)?;
-        let bones = read_indexes(header.bone_indexes(), data).collect::<Result<_>>()?;
+        let bones: Vec<Bone> = read_indexes(header.bone_indexes(), data).collect::<Result<_>>()?;
+        let bone_names = bones
+            .iter()
+            .zip(header.bone_indexes())
+            .map(|(bone, index)| read_string(&data[index..], bone.sz_name_index))
+            .collect::<Result<_>>()?;
+        let bone_count = bones.len();
+        let animation_block_file = if header.anim_blocks_name_index > 0 {
+            read_string(data, header.anim_blocks_name_index)?
+        } else {
+            String::new()
+        };
+        let key_values = header
+            .key_value_indexes()
+            .map(|index| {
+                data.get(index).copied().ok_or(ModelError::OutOfBounds {
+                    data: "KeyValues",
+                    offset: index,
+                })
+            })
+            .take_while(|byte| !matches!(byte, Ok(0)))
+            .collect::<Result<Vec<u8>>>()?;
+        let key_values =
+            String::from_utf8(key_values).map_err(|err| StringError::NonUTF8(err.utf8_error()))?;
+        let header2_data = header
+            .header2_index()
+            .map(|index| {
+                data.get(index..).ok_or(ModelError::OutOfBounds {
+                    data: "StudioHHeader2",
+                    offset: index,
+                })
+            })
+            .transpose()?;
+        let header2 = header2_data
+            .map(<StudioHHeader2 as Readable>::read)
+            .transpose()?;
+        let name = match (header2_data, header2) {
+            (Some(data), Some(header2)) if header2.sz_name_index > 0 => {
+                read_string(data, header2.sz_name_index)?
+            }
+            _ => FixedString::<64>::try_from(header.name)?.to_string(),
+        };
+        let (source_bone_transforms, linear_bones, bone_flex_drivers) =
+            match (header2_data, header2) {
+                (Some(data), Some(header2)) => (
+                    read_relative(data, header2.source_bone_transform_indexes())?,
+                    usize::try_from(header2.linear_bone_index)
+                        .ok()
+                        .filter(|index| *index > 0)
+                        .map(|index| {
+                            let data = data.get(index..).ok_or(ModelError::OutOfBounds {
+                                data: "LinearBones",
+                                offset: index,
+                            })?;
+                            let header = <LinearBoneHeader as Readable>::read(data)?;
+                            LinearBones::read(data, header)
+                        })
+                        .transpose()?,
+                    read_relative(data, header2.bone_flex_driver_indexes())?,
+                ),
+                _ => (Vec::new(), None, Vec::new()),
+            };
+        let node_names = read_indexes::<_, i32>(header.local_node_name_indexes(), data)
+            .map(|offset| read_string(data, offset?))
+            .collect::<Result<_>>()?;
+        let texture_dirs = read_indexes::<_, i32>(header.texture_dir_indexes(), data)
+            .map(|offset| read_string(data, offset?))
+            .collect::<Result<_>>()?;
+        let mut body_parts: Vec<BodyPart> = header
+            .body_part_indexes()
+            .map(|index| {
+                let data = data.get(index..).ok_or(ModelError::OutOfBounds {
+                    data: "BodyPart",
+                    offset: index,
+                })?;
+                let header = <BodyPartHeader as Readable>::read(data)?;
+                BodyPart::read(data, header)
+            })
+            .collect::<Result<_>>()?;
+        if header
+            .flags
+            .contains(ModelFlags::VERT_ANIM_FIXED_POINT_SCALE)
+        {
+            body_parts
+                .iter_mut()
+                .flat_map(|part| &mut part.models)
+                .flat_map(|model| &mut model.meshes)
+                .flat_map(|mesh| &mut mesh.flexes)
+                .flat_map(|flex| &mut flex.vertices)
+                .for_each(|vertex| vertex.decode(Some(header.vert_anim_fixed_point_scale)));
+        }
         Ok(Mdl {
             bones,
-            body_parts: header
-                .body_part_indexes()
+            bone_names,
+            body_parts,
+            textures: read_relative(data, header.texture_indexes())?,
+            texture_dirs,
+            skin_table: SkinTable::read(data, &header)?,
+            hitbox_sets: read_relative(data, header.hitbox_indexes())?,
+            attachments: read_relative(data, header.attachment_indexes())?,
+            animations: read_relative(data, header.local_animation_indexes())?,
+            animation_block_file,
+            animation_blocks: read_indexes(header.animation_block_indexes(), data)
+                .collect::<Result<_>>()?,
+            sequences: header
+                .local_sequence_indexes()
+                .map(|index| {
+                    let data = data.get(index..).ok_or(ModelError::OutOfBounds {
+                        data: "Sequence",
+                        offset: index,
+                    })?;
+                    let header = <SequenceDescriptionHeader as Readable>::read(data)?;
+                    Sequence::read(data, header, bone_count)
+                })
+                .collect::<Result<_>>()?,
+            pose_parameters: read_relative(data, header.local_pose_param_indexes())?,
+            include_models: read_relative(data, header.include_model_indexes())?,
+            flex_descriptors: read_relative(data, header.flex_descriptor_indexes())?,
+            flex_controllers: read_relative(data, header.flex_controller_indexes())?,
+            flex_rules: read_relative(data, header.flex_rule_indexes())?,
+            bone_controllers: read_relative(data, header.bone_controller_indexes())?,
+            mouths: read_relative(data, header.mouth_indexes())?,
+            key_values,
+            node_names,
+            node_transitions: read_indexes(header.local_node_indexes(), data)
+                .collect::<Result<_>>()?,
+            ik_chains: read_relative(data, header.ik_chain_indexes())?,
+            ik_locks: read_relative(data, header.ik_lock_indexes())?,
+            flex_controller_uis: header
+                .flex_controller_ui_indexes()
                 .map(|index| {
                     let data = data.get(index..).ok_or(ModelError::OutOfBounds {
-                        data: "BodyPart",
+                        data: "FlexControllerUi",
                         offset: index,
                     })?;
-                    let header = <BodyPartHeader as Readable>::read(data)?;
-                    BodyPart::read(data, header)
+                    let ui_header = <FlexControllerUiHeader as Readable>::read(data)?;
+                    FlexControllerUi::read(data, ui_header, index, &header)
                 })
                 .collect::<Result<_>>()?,
+            source_bone_transforms,
+            linear_bones,
+            bone_flex_drivers,
             header,
+            header2,
+            name,
         })
     }
+
+    fn bone(&self, bone: i32) -> Option<&Bone> {
+        self.bones.get(usize::try_from(bone).ok()?)
+    }
+
+    pub fn bone_name(&self, bone: usize) -> Option<&str> {
+        self.bone_names.get(bone).map(String::as_str)
+    }
+
+    /// Load the animation data for all animations stored in the animation blocks of the ani file
+    pub fn load_animation_blocks(&mut self, ani: &Ani) -> Result<()> {
+        let blocks = &self.animation_blocks;
+        self.animations
+            .iter_mut()
+            .try_for_each(|animation| animation.load_blocks(blocks, ani))
+    }
+
+    /// Get the next node to move to when transitioning from one node towards another
+    ///
+    /// Nodes are numbered from 1 as in [`Sequence::entry_node`] and [`Sequence::exit_node`],
+    /// returns `None` if either node doesn't exist or there is no route between them
+    pub fn transition(&self, from_node: i32, to_node: i32) -> Option<i32> {
+        let count = self.node_names.len();
+        let from = usize::try_from(from_node).ok()?.checked_sub(1)?;
+        let to = usize::try_from(to_node).ok()?.checked_sub(1)?;
+        if from >= count || to >= count {
+            return None;
+        }
+        self.node_transitions
+            .get(from * count + to)
+            .filter(|node| **node != 0)
+            .map(|node| *node as i32)
+    }
+
+    /// Check that all ik chains reference existing bones and all ik locks reference existing chains
+    pub fn validate_ik(&self) -> Result<()> {
+        for (index, chain) in self.ik_chains.iter().enumerate() {
+            for link in &chain.links {
+                if self.bone(link.bone).is_none() {
+                    return Err(ModelError::InvalidIkChainBone {
+                        chain: index,
+                        bone: link.bone,
+                    });
+                }
+            }
+        }
+        for (index, lock) in self.ik_locks.iter().enumerate() {
+            let chain = usize::try_from(lock.chain).ok();
+            if chain.and_then(|chain| self.ik_chains.get(chain)).is_none() {
+                return Err(ModelError::InvalidIkLockChain {
+                    lock: index,
+                    chain: lock.chain,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Calculate the weight of every flex descriptor from the values of the flex controllers
+    ///
+    /// The values are in the range of each controller, missing values default to 0
+    pub fn flex_weights(&self, controller_values: &[f32]) -> Vec<f32> {
+        evaluate_flex_rules(
+            &self.flex_rules,
+            &self.flex_controllers,
+            controller_values,
+            self.flex_descriptors.len(),
+        )
+    }
+
+    /// Find a flex controller by its name, ignoring case
+    pub fn find_flex_controller(&self, name: &str) -> Option<usize> {
+        self.flex_controllers
+            .iter()
+            .position(|controller| controller.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Find a sequence by its label, ignoring case
+    pub fn find_sequence(&self, label: &str) -> Option<usize> {
+        self.sequences
+            .iter()
+            .position(|sequence| sequence.label.eq_ignore_ascii_case(label))
+    }
+
+    /// Find a pose parameter by its name, ignoring case
+    pub fn find_pose_parameter(&self, name: &str) -> Option<usize> {
+        self.pose_parameters
+            .iter()
+            .position(|parameter| parameter.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Get the blended local transform of every bone at a point (0..1) in the cycle of a sequence
+    ///
+    /// `pose_parameters` are the normalized (0..1) values for each pose parameter, see [`PoseParameter::normalize`]
+    pub fn sequence_pose(
+        &self,
+        sequence: usize,
+        cycle: f32,
+        pose_parameters: &[f32],
+    ) -> Result<Vec<BonePose>> {
+        self.sequences
+            .get(sequence)
+            .ok_or(ModelError::OutOfBounds {
+                data: "Sequence",
+                offset: sequence,
+            })?
+            .pose(self, cycle, pose_parameters)
+    }
+
+    /// Get the controllers attached to a bone, with the axis they control
+    ///
+    /// The axes are ordered as X, Y, Z, XR, YR, ZR
+    pub fn bone_controllers_for_bone(
+        &self,
+        bone: usize,
+    ) -> impl Iterator<Item = (usize, &BoneController)> {
+        self.bones
+            .get(bone)
+            .into_iter()
+            .flat_map(|bone| bone.bone_controller.iter().enumerate())
+            .filter_map(|(axis, controller)| {
+                let controller = self
+                    .bone_controllers
+                    .get(usize::try_from(*controller).ok()?)?;
+                Some((axis, controller))
+            })
+    }
+
+    /// The `$keyvalues` text of the model, empty if the model has no keyvalues
+    pub fn keyvalues_text(&self) -> &str {
+        &self.key_values
+    }
+
+    /// Parse the `$keyvalues` of the model, such as `prop_data`
+    pub fn keyvalues(&self) -> Result<KeyValues> {
+        KeyValues::parse(&self.key_values)
+    }
+
+    /// Get the bone hierarchy of the model
+    ///
+    /// This fails if any bone references a parent that doesn't exist or if the hierarchy contains a cycle
+    pub fn skeleton(&self) -> Result<Skeleton<'_>> {
+        Skeleton::new(self)
+    }
+
+    /// Get the local and world transforms for every bone in the bind pose
+    pub fn bind_pose(&self) -> Result<BindPose> {
+        Ok(self.skeleton()?.bind_pose())
+    }
+
+    /// Get the texture used by a mesh in a skin
+    ///
+    /// Skins that don't exist in the model fall back to the default skin
+    pub fn mesh_texture(&self, mesh: &Mesh, skin: usize) -> Option<&Texture> {
+        let reference = usize::try_from(mesh.material).ok()?;
+        let texture = self
+            .skin_table
+            .texture_index(skin, reference)
+            .unwrap_or(reference);
+        self.textures.get(texture)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct BodyPart {
+    pub name: String,
     pub name_index: i32,
+    /// Multiplier for the model index of this body part in the packed `body` value
+    pub base: i32,
     pub models: Vec<Model>,
 }
 
@@ -50,12 +420,69 @@ impl ReadRelative for BodyPart {
 
     fn read(data: &[u8], header: Self::Header) -> Result<Self> {
         Ok(BodyPart {
+            name: read_string(data, header.name_index)?,
             models: read_relative(data, header.model_indexes())?,
             name_index: header.name_index,
+            base: header.base,
         })
     }
 }
 
+/// The selected model for every body part of a model
+///
+/// Body parts without an explicit selection use their first model
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BodyGroupSelection {
+    models: Vec<usize>,
+}
+
+impl BodyGroupSelection {
+    /// Create a selection from the model index for each body part
+    pub fn new(models: Vec<usize>) -> Self {
+        BodyGroupSelection { models }
+    }
+
+    /// Unpack the `body` value as used by the engine into the selected model for each body part
+    pub fn from_body(body_parts: &[BodyPart], body: i32) -> Self {
+        BodyGroupSelection {
+            models: body_parts
+                .iter()
+                .map(|part| {
+                    let base = part.base.max(1);
+                    let count = (part.models.len() as i32).max(1);
+                    ((body / base) % count) as usize
+                })
+                .collect(),
+        }
+    }
+
+    /// Pack the selection into the `body` value as used by the engine
+    pub fn body(&self, body_parts: &[BodyPart]) -> i32 {
+        body_parts
+            .iter()
+            .zip(self.models.iter())
+            .map(|(part, model)| part.base * *model as i32)
+            .sum()
+    }
+
+    /// The selected model index for a body part
+    pub fn model(&self, body_part: usize) -> usize {
+        self.models.get(body_part).copied().unwrap_or_default()
+    }
+
+    pub fn set(&mut self, body_part: usize, model: usize) {
+        if self.models.len() <= body_part {
+            self.models.resize(body_part + 1, 0);
+        }
+        self.models[body_part] = model;
+    }
+
+    pub fn with(mut self, body_part: usize, model: usize) -> Self {
+        self.set(body_part, model);
+        self
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Model {
     pub name: FixedString<64>,
@@ -64,6 +491,7 @@ pub struct Model {
     pub meshes: Vec<Mesh>,
     /// Base offset of the model's vertices
     pub vertex_offset: i32,
+    pub eyeballs: Vec<Eyeball>,
 }
 
 impl ReadRelative for Model {
@@ -76,21 +504,138 @@ impl ReadRelative for Model {
             ty: header.ty,
             bounding_radius: header.bounding_radius,
             vertex_offset: header.vertex_index / (size_of::<Vertex>() as i32),
+            eyeballs: read_relative(data, header.eyeball_indexes())?,
         })
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Mesh {
+    /// Skin reference of the mesh, remapped to a texture through the skin family
+    pub material: i32,
+    pub material_type: i32,
+    pub material_param: i32,
+    pub id: i32,
+    pub center: Vector,
     pub vertex_offset: i32,
+    /// Number of vertices used by the mesh when rendering with each root lod
+    pub lod_vertex_count: [i32; 8],
+    /// Morph targets of the mesh
+    pub flexes: Vec<Flex>,
 }
 
 impl ReadRelative for Mesh {
     type Header = MeshHeader;
 
-    fn read(_data: &[u8], header: Self::Header) -> Result<Self> {
+    fn read(data: &[u8], header: Self::Header) -> Result<Self> {
         Ok(Mesh {
+            flexes: read_relative(data, header.flex_indexes())?,
+            material: header.material,
+            material_type: header.material_type,
+            material_param: header.material_param,
+            id: header.mesh_id,
+            center: header.center,
             vertex_offset: header.vertex_index,
+            lod_vertex_count: header.vertex_data.lod_vertex_count,
+        })
+    }
+}
+
+/// Material type of meshes rendered as an eyeball
+const MATERIAL_TYPE_EYEBALL: i32 = 1;
+
+impl Mesh {
+    /// Index of the eyeball in the model if the mesh is an eye
+    pub fn eyeball(&self) -> Option<usize> {
+        (self.material_type == MATERIAL_TYPE_EYEBALL)
+            .then(|| usize::try_from(self.material_param).ok())
+            .flatten()
+    }
+
+    /// Number of vertices used by the mesh when rendering with a root lod
+    pub fn vertex_count(&self, root_lod: usize) -> usize {
+        self.lod_vertex_count
+            .get(root_lod)
+            .map(|count| (*count).max(0) as usize)
+            .unwrap_or_default()
+    }
+}
+
+impl<'a> Handle<'a, Mesh> {
+    /// The texture used by the mesh in a skin
+    pub fn texture(&self, skin: usize) -> Option<&'a Texture> {
+        self.mdl().mesh_texture(self.as_ref(), skin)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Texture {
+    /// Name of the material, relative to one of the texture directories
+    pub name: String,
+    pub flags: i32,
+}
+
+impl ReadRelative for Texture {
+    type Header = TextureHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self> {
+        Ok(Texture {
+            name: read_string(data, header.name_index)?,
+            flags: header.flags,
         })
     }
 }
+
+/// Texture remapping for each skin family of the model
+///
+/// Every skin family assigns a texture index to each skin reference used by the meshes
+#[derive(Debug, Clone, Default)]
+pub struct SkinTable {
+    reference_count: usize,
+    textures: Vec<i16>,
+}
+
+impl SkinTable {
+    fn read(data: &[u8], header: &StudioHeader) -> Result<Self> {
+        let header = *header;
+        let indexes = (0..header.skin_r_family_count)
+            .flat_map(move |family| header.skin_reference_indexes(family));
+        let textures = read_indexes(indexes, data).collect::<Result<_>>()?;
+        Ok(SkinTable {
+            reference_count: header.skin_reference_count.max(0) as usize,
+            textures,
+        })
+    }
+
+    pub fn family_count(&self) -> usize {
+        self.textures
+            .len()
+            .checked_div(self.reference_count)
+            .unwrap_or_default()
+    }
+
+    pub fn reference_count(&self) -> usize {
+        self.reference_count
+    }
+
+    /// Texture indexes for every skin reference in the family
+    pub fn family(&self, family: usize) -> Option<&[i16]> {
+        let start = family.checked_mul(self.reference_count)?;
+        self.textures.get(start..start + self.reference_count)
+    }
+
+    pub fn families(&self) -> impl Iterator<Item = &[i16]> {
+        self.textures.chunks(self.reference_count.max(1))
+    }
+
+    /// Get the texture index for a skin reference, falling back to the default family for unknown skins
+    pub fn texture_index(&self, family: usize, reference: usize) -> Option<usize> {
+        let family = if family < self.family_count() {
+            family
+        } else {
+            0
+        };
+        let texture = *self.family(family)?.get(reference)?;
+        usize::try_from(texture).ok()
+    }
+}
@@ -0,0 +1,258 @@
+use crate::{index_range, Quaternion, Vector};
+use bitflags::bitflags;
+use bytemuck::{Pod, Zeroable};
+use std::mem::size_of;
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+#[allow(dead_code)]
+pub struct AnimationDescriptionHeader {
+    base_pointer: i32,
+    pub name_index: i32,
+    pub fps: f32,
+    pub flags: AnimationFlags,
+    pub frame_count: i32,
+
+    // piecewise movement
+    movement_count: i32,
+    movement_index: i32,
+
+    unused1: [i32; 6],
+
+    pub animation_block: i32,
+    pub animation_index: i32, // non-zero when anim data isn't in sections
+
+    ik_rule_count: i32,
+    ik_rule_index: i32,
+    animation_block_ik_rule_index: i32,
+
+    local_hierarchy_count: i32,
+    local_hierarchy_index: i32,
+
+    section_index: i32,
+    pub section_frames: i32, // number of frames used in each fast lookup section, zero if not used
+
+    pub zero_frame_span: i16,  // frames per span
+    pub zero_frame_count: i16, // number of spans
+    pub zero_frame_index: i32,
+
+    zero_frame_stall_time: f32,
+}
+
+static_assertions::const_assert_eq!(size_of::<AnimationDescriptionHeader>(), 100);
+
+impl AnimationDescriptionHeader {
+    pub fn movement_indexes(&self) -> impl Iterator<Item = usize> {
+        index_range(
+            self.movement_index,
+            self.movement_count,
+            size_of::<Movement>(),
+        )
+    }
+
+    pub fn section_indexes(&self) -> impl Iterator<Item = usize> {
+        let count = if self.section_frames > 0 && self.section_index > 0 {
+            self.frame_count / self.section_frames + 2
+        } else {
+            0
+        };
+        index_range(
+            self.section_index,
+            count,
+            size_of::<AnimationSectionHeader>(),
+        )
+    }
+}
+
+bitflags! {
+    #[derive(Zeroable, Pod)]
+    #[repr(C)]
+    pub struct AnimationFlags: u32 {
+        const LOOPING =     0x0001; // ending frame should be the same as the starting frame
+        const SNAP =        0x0002; // do not interpolate between previous animation and this one
+        const DELTA =       0x0004; // this sequence "adds" to the base sequences, not slerp blends
+        const AUTOPLAY =    0x0008; // temporary flag that forces the sequence to always play
+        const POST =        0x0010;
+        const ALL_ZEROS =   0x0020; // this animation/sequence has no real animation data
+        const CYCLE_POSE =  0x0080; // cycle index is taken from a pose parameter index
+        const REALTIME =    0x0100; // cycle index is taken from a real-time clock, not the animations cycle index
+        const LOCAL =       0x0200; // sequence has a local context sequence
+        const HIDDEN =      0x0400; // don't show in default selection views
+        const OVERRIDE =    0x0800; // a forward declared sequence (empty)
+        const ACTIVITY =    0x1000; // Has been updated at runtime to activity index
+        const EVENT =       0x2000; // Has been updated at runtime to event index
+        const WORLD =       0x4000; // sequence blends in worldspace
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct Movement {
+    pub end_frame: i32,
+    pub motion_flags: MotionFlags,
+    pub v0: f32,          // velocity at start of block
+    pub v1: f32,          // velocity at end of block
+    pub angle: f32,       // YAW rotation at end of this blocks movement
+    pub vector: Vector,   // movement vector relative to this blocks initial angle
+    pub position: Vector, // relative to start of animation
+}
+
+static_assertions::const_assert_eq!(size_of::<Movement>(), 44);
+
+bitflags! {
+    #[derive(Zeroable, Pod)]
+    #[repr(C)]
+    pub struct MotionFlags: u32 {
+        const X =       0x00000001;
+        const Y =       0x00000002;
+        const Z =       0x00000004;
+        const XR =      0x00000008;
+        const YR =      0x00000010;
+        const ZR =      0x00000020;
+        const LX =      0x00000040;
+        const LY =      0x00000080;
+        const LZ =      0x00000100;
+        const LXR =     0x00000200;
+        const LYR =     0x00000400;
+        const LZR =     0x00000800;
+        const LINEAR =  0x00001000;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct AnimationSectionHeader {
+    pub animation_block: i32,
+    pub animation_index: i32,
+}
+
+static_assertions::const_assert_eq!(size_of::<AnimationSectionHeader>(), 8);
+
+/// Range of an animation block in the ani file
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct AnimationBlock {
+    pub data_start: i32,
+    pub data_end: i32,
+}
+
+static_assertions::const_assert_eq!(size_of::<AnimationBlock>(), 8);
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct BoneAnimationHeader {
+    pub bone: u8,
+    pub flags: BoneAnimationFlags,
+    pub next_offset: i16,
+}
+
+static_assertions::const_assert_eq!(size_of::<BoneAnimationHeader>(), 4);
+
+bitflags! {
+    #[derive(Zeroable, Pod)]
+    #[repr(C)]
+    pub struct BoneAnimationFlags: u8 {
+        const RAW_POS =     0x01; // Vector48
+        const RAW_ROT =     0x02; // Quaternion48
+        const ANIM_POS =    0x04; // mstudioanim_valueptr_t
+        const ANIM_ROT =    0x08; // mstudioanim_valueptr_t
+        const DELTA =       0x10;
+        const RAW_ROT2 =    0x20; // Quaternion64
+    }
+}
+
+/// Offsets to the animated values for each axis, relative to the start of this struct
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct AnimationValuePointer {
+    pub offset: [i16; 3],
+}
+
+static_assertions::const_assert_eq!(size_of::<AnimationValuePointer>(), 6);
+
+/// Run length encoded animation value
+///
+/// Each run starts with a header value containing the number of stored values and the number of frames
+/// covered by the run, followed by the stored values. Frames after the last stored value repeat it.
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct AnimationValue(pub i16);
+
+impl AnimationValue {
+    /// Number of values stored in a run, when used as run header
+    pub fn valid(self) -> u8 {
+        self.0.to_le_bytes()[0]
+    }
+
+    /// Number of frames covered by a run, when used as run header
+    pub fn total(self) -> u8 {
+        self.0.to_le_bytes()[1]
+    }
+
+    pub fn value(self) -> i16 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct Quaternion48 {
+    x: u16,
+    y: u16,
+    z_wneg: u16,
+}
+
+impl From<Quaternion48> for Quaternion {
+    fn from(q: Quaternion48) -> Self {
+        let x = (q.x as i32 - 32768) as f32 * (1.0 / 32768.0);
+        let y = (q.y as i32 - 32768) as f32 * (1.0 / 32768.0);
+        let z = ((q.z_wneg & 0x7FFF) as i32 - 16384) as f32 * (1.0 / 16384.0);
+        let w = (1.0 - x * x - y * y - z * z).max(0.0).sqrt();
+        let w = if q.z_wneg & 0x8000 != 0 { -w } else { w };
+        Quaternion { x, y, z, w }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct Quaternion64([u8; 8]);
+
+impl From<Quaternion64> for Quaternion {
+    fn from(q: Quaternion64) -> Self {
+        let bits = u64::from_le_bytes(q.0);
+        let component = |shift: u32| ((bits >> shift) & 0x1F_FFFF) as i32 - 1048576;
+        let x = component(0) as f32 * (1.0 / 1048576.5);
+        let y = component(21) as f32 * (1.0 / 1048576.5);
+        let z = component(42) as f32 * (1.0 / 1048576.5);
+        let w = (1.0 - x * x - y * y - z * z).max(0.0).sqrt();
+        let w = if bits >> 63 != 0 { -w } else { w };
+        Quaternion { x, y, z, w }
+    }
+}
+
+/// Vector stored as 3 half precision floats
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct Vector48([u16; 3]);
+
+impl From<Vector48> for Vector {
+    fn from(v: Vector48) -> Self {
+        Vector {
+            x: f16_to_f32(v.0[0]),
+            y: f16_to_f32(v.0[1]),
+            z: f16_to_f32(v.0[2]),
+        }
+    }
+}
+
+pub fn f16_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1F) as i32;
+    let mantissa = (half & 0x3FF) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1F if mantissa == 0.0 => f32::INFINITY,
+        0x1F => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
@@ -0,0 +1,113 @@
+use crate::index_range;
+use bytemuck::{Pod, Zeroable};
+use std::mem::size_of;
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct FlexDescriptorHeader {
+    pub name_index: i32,
+}
+
+static_assertions::const_assert_eq!(size_of::<FlexDescriptorHeader>(), 4);
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct FlexControllerHeader {
+    pub type_index: i32,
+    pub name_index: i32,
+    pub local_to_global: i32, // assigned at runtime
+    pub min: f32,
+    pub max: f32,
+}
+
+static_assertions::const_assert_eq!(size_of::<FlexControllerHeader>(), 20);
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct FlexRuleHeader {
+    pub flex: i32,
+    op_count: i32,
+    op_index: i32,
+}
+
+static_assertions::const_assert_eq!(size_of::<FlexRuleHeader>(), 12);
+
+impl FlexRuleHeader {
+    pub fn op_indexes(&self) -> impl Iterator<Item = usize> {
+        index_range(self.op_index, self.op_count, size_of::<FlexOpHeader>())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct FlexOpHeader {
+    pub op: i32,
+    pub data: i32, // index or float value depending on the op
+}
+
+static_assertions::const_assert_eq!(size_of::<FlexOpHeader>(), 8);
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+#[allow(dead_code)]
+pub struct FlexControllerUiHeader {
+    pub name_index: i32,
+    // offsets of the controllers, relative to the start of this struct
+    pub controller_index: [i32; 3],
+    pub remap_type: u8,
+    pub stereo: u8,
+    unused: [u8; 2],
+}
+
+static_assertions::const_assert_eq!(size_of::<FlexControllerUiHeader>(), 20);
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+#[allow(dead_code)]
+pub struct FlexHeader {
+    pub flex_descriptor: i32,
+    // weight ramp of the flex, see RampFlexWeight
+    pub target: [f32; 4],
+    vertex_count: i32,
+    vertex_index: i32,
+    pub flex_pair: i32, // second flex descriptor for stereo flexes
+    pub vertex_animation_type: u8,
+    unused_char: [u8; 3],
+    unused: [i32; 6],
+}
+
+static_assertions::const_assert_eq!(size_of::<FlexHeader>(), 60);
+
+pub const VERTEX_ANIMATION_WRINKLE: u8 = 1;
+
+impl FlexHeader {
+    /// Size of each vertex animation, wrinkle animations store an additional wrinkle delta
+    pub fn vertex_animation_size(&self) -> usize {
+        if self.vertex_animation_type == VERTEX_ANIMATION_WRINKLE {
+            size_of::<VertexAnimationHeader>() + size_of::<i16>()
+        } else {
+            size_of::<VertexAnimationHeader>()
+        }
+    }
+
+    pub fn vertex_indexes(&self) -> impl Iterator<Item = usize> {
+        index_range(
+            self.vertex_index,
+            self.vertex_count,
+            self.vertex_animation_size(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct VertexAnimationHeader {
+    pub index: u16,
+    pub speed: u8, // 255/max_length_of_delta
+    pub side: u8,  // 255/left_right
+    // float16 values, or fixed point values if the model uses VERT_ANIM_FIXED_POINT_SCALE
+    pub delta: [u16; 3],
+    pub normal_delta: [u16; 3],
+}
+
+static_assertions::const_assert_eq!(size_of::<VertexAnimationHeader>(), 16);
@@ -1,3 +1,8 @@
+use crate::mdl::raw::animation::{AnimationBlock, AnimationDescriptionHeader};
+use crate::mdl::raw::flex::{
+    FlexControllerHeader, FlexControllerUiHeader, FlexDescriptorHeader, FlexRuleHeader,
+};
+use crate::mdl::raw::sequence::{PoseParameterHeader, SequenceDescriptionHeader};
 use crate::mdl::raw::*;
 use crate::mdl::Bone;
 use crate::{index_range, Vector};
@@ -72,7 +77,7 @@ pub struct StudioHeader {
     attachment_count: i32,
     attachment_offset: i32,
 
-    // Node values appear to be single bytes, while their names are null-terminated strings.
+    // Transition table with a byte for every pair of nodes, names are offsets to null-terminated strings.
     local_node_count: i32,
     local_node_index: i32,
     local_node_name_index: i32,
@@ -136,7 +141,7 @@ pub struct StudioHeader {
     // Note that the SDK only compiles as 32-bit, so an int and a pointer are the same size (4 bytes)
 
     // mstudioanimblock_t
-    anim_blocks_name_index: i32,
+    pub anim_blocks_name_index: i32, // name of the ani file containing the animation blocks
     anim_blocks_count: i32,
     anim_blocks_index: i32,
 
@@ -213,27 +218,63 @@ impl StudioHeader {
     }
 
     pub fn bone_controller_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.bone_controller_offset, self.bone_controller_count, 1)
+        index_range(
+            self.bone_controller_offset,
+            self.bone_controller_count,
+            size_of::<BoneControllerHeader>(),
+        )
     }
 
     pub fn hitbox_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.hitbox_offset, self.hitbox_count, 1)
+        index_range(
+            self.hitbox_offset,
+            self.hitbox_count,
+            size_of::<HitboxSetHeader>(),
+        )
     }
 
     pub fn local_animation_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.local_animation_offset, self.local_animation_count, 1)
+        index_range(
+            self.local_animation_offset,
+            self.local_animation_count,
+            size_of::<AnimationDescriptionHeader>(),
+        )
     }
 
     pub fn local_sequence_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.local_seq_offset, self.local_seq_count, 1)
+        index_range(
+            self.local_seq_offset,
+            self.local_seq_count,
+            size_of::<SequenceDescriptionHeader>(),
+        )
     }
 
     pub fn texture_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.texture_offset, self.texture_count, 1)
+        index_range(
+            self.texture_offset,
+            self.texture_count,
+            size_of::<TextureHeader>(),
+        )
     }
 
     pub fn texture_dir_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.texture_dir_offset, self.texture_dir_count, 1)
+        index_range(
+            self.texture_dir_offset,
+            self.texture_dir_count,
+            size_of::<i32>(),
+        )
+    }
+
+    /// Indexes of the texture index for each skin reference in a skin family
+    ///
+    /// Families with an overflowing offset are treated as empty
+    pub fn skin_reference_indexes(&self, family: i32) -> impl Iterator<Item = usize> {
+        let offset = family
+            .checked_mul(self.skin_reference_count)
+            .and_then(|offset| offset.checked_mul(size_of::<i16>() as i32))
+            .and_then(|offset| offset.checked_add(self.skin_reference_index));
+        let count = offset.map_or(0, |_| self.skin_reference_count);
+        index_range(offset.unwrap_or_default(), count, size_of::<i16>())
     }
 
     pub fn body_part_indexes(&self) -> impl Iterator<Item = usize> {
@@ -245,39 +286,77 @@ impl StudioHeader {
     }
 
     pub fn attachment_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.attachment_offset, self.attachment_count, 1)
+        index_range(
+            self.attachment_offset,
+            self.attachment_count,
+            size_of::<AttachmentHeader>(),
+        )
     }
 
+    /// Indexes of the transition table, with `local_node_count` entries for every node
     pub fn local_node_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.local_node_index, self.local_node_count, 1)
+        // overflowing node counts are treated as empty
+        let count = self
+            .local_node_count
+            .checked_mul(self.local_node_count)
+            .unwrap_or_default();
+        index_range(self.local_node_index, count, size_of::<u8>())
     }
 
     pub fn local_node_name_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.local_node_name_index, self.local_node_count, 1)
+        index_range(
+            self.local_node_name_index,
+            self.local_node_count,
+            size_of::<i32>(),
+        )
     }
 
     pub fn flex_descriptor_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.flex_desc_index, self.flex_desc_count, 1)
+        index_range(
+            self.flex_desc_index,
+            self.flex_desc_count,
+            size_of::<FlexDescriptorHeader>(),
+        )
     }
 
     pub fn flex_controller_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.flex_controller_index, self.flex_controller_count, 1)
+        index_range(
+            self.flex_controller_index,
+            self.flex_controller_count,
+            size_of::<FlexControllerHeader>(),
+        )
     }
 
     pub fn flex_rule_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.flex_rules_index, self.flex_rules_count, 1)
+        index_range(
+            self.flex_rules_index,
+            self.flex_rules_count,
+            size_of::<FlexRuleHeader>(),
+        )
     }
 
     pub fn ik_chain_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.ik_chain_index, self.ik_chain_count, 1)
+        index_range(
+            self.ik_chain_index,
+            self.ik_chain_count,
+            size_of::<IkChainHeader>(),
+        )
     }
 
     pub fn mouth_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.mouths_index, self.mouths_count, 1)
+        index_range(
+            self.mouths_index,
+            self.mouths_count,
+            size_of::<MouthHeader>(),
+        )
     }
 
     pub fn local_pose_param_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.local_pose_param_index, self.local_pose_param_count, 1)
+        index_range(
+            self.local_pose_param_index,
+            self.local_pose_param_count,
+            size_of::<PoseParameterHeader>(),
+        )
     }
 
     pub fn key_value_indexes(&self) -> impl Iterator<Item = usize> {
@@ -285,17 +364,32 @@ impl StudioHeader {
     }
 
     pub fn ik_lock_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.ik_lock_index, self.ik_lock_count, 1)
+        index_range(
+            self.ik_lock_index,
+            self.ik_lock_count,
+            size_of::<IkLockHeader>(),
+        )
     }
 
     pub fn include_model_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.include_model_index, self.include_model_count, 1)
+        index_range(
+            self.include_model_index,
+            self.include_model_count,
+            size_of::<IncludeModelHeader>(),
+        )
     }
 
     pub fn animation_block_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(self.anim_blocks_index, self.anim_blocks_count, 1)
+        index_range(
+            self.anim_blocks_index,
+            self.anim_blocks_count,
+            size_of::<AnimationBlock>(),
+        )
     }
 
+    #[deprecated(
+        note = "the animation blocks share a single file name, use `anim_blocks_name_index` instead"
+    )]
     pub fn animation_block_name_indexes(&self) -> impl Iterator<Item = usize> {
         index_range(self.anim_blocks_name_index, self.anim_blocks_count, 1)
     }
@@ -304,7 +398,7 @@ impl StudioHeader {
         index_range(
             self.flex_controller_ui_index,
             self.flex_controller_ui_count,
-            1,
+            size_of::<FlexControllerUiHeader>(),
         )
     }
 }
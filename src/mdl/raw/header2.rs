@@ -1,5 +1,10 @@
-use std::ops::Range;
+use crate::{index_range, Matrix3x4};
+use bytemuck::{Pod, Zeroable};
+use std::mem::size_of;
 
+/// Secondary header, offsets are relative to the start of this header
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
 pub struct StudioHHeader2 {
     source_bone_transform_count: i32,
     source_bone_transform_index: i32,
@@ -16,24 +21,104 @@ pub struct StudioHHeader2 {
     bone_flex_driver_index: i32,
 
     #[allow(dead_code)]
-    reserved: [i32; 56],
+    reserved: [[i32; 8]; 7],
 }
 
+static_assertions::const_assert_eq!(size_of::<StudioHHeader2>(), 256);
+
 impl StudioHHeader2 {
-    pub fn source_bone_transforms(&self) -> Range<i32> {
-        self.source_bone_transform_index
-            ..(self.source_bone_transform_index + self.source_bone_transform_count)
+    pub fn source_bone_transform_indexes(&self) -> impl Iterator<Item = usize> {
+        index_range(
+            self.source_bone_transform_index,
+            self.source_bone_transform_count,
+            size_of::<SourceBoneTransformHeader>(),
+        )
     }
 
-    pub fn bone_flex_drivers(&self) -> Range<i32> {
-        self.bone_flex_driver_index..(self.bone_flex_driver_index + self.bone_flex_driver_count)
+    pub fn bone_flex_driver_indexes(&self) -> impl Iterator<Item = usize> {
+        index_range(
+            self.bone_flex_driver_index,
+            self.bone_flex_driver_count,
+            size_of::<BoneFlexDriverHeader>(),
+        )
     }
 
+    /// Cosine of the maximum angle the eyes can turn, defaults to 30 degrees
     pub fn max_eye_deflection(&self) -> f32 {
         if self.fl_max_exe_deflection == 0.0 {
-            (30.0f32).cos()
+            30.0f32.to_radians().cos()
         } else {
             self.fl_max_exe_deflection
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct SourceBoneTransformHeader {
+    pub name_index: i32,
+    pub pre_transform: Matrix3x4,
+    pub post_transform: Matrix3x4,
+}
+
+static_assertions::const_assert_eq!(size_of::<SourceBoneTransformHeader>(), 100);
+
+/// Bone data stored as separate arrays for each field
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+#[allow(dead_code)]
+pub struct LinearBoneHeader {
+    pub bone_count: i32,
+    pub flags_index: i32,
+    pub parent_index: i32,
+    pub pos_index: i32,
+    pub quaternion_index: i32,
+    pub rot_index: i32,
+    pub pose_to_bone_index: i32,
+    pub pos_scale_index: i32,
+    pub rot_scale_index: i32,
+    pub q_alignment_index: i32,
+    unused: [i32; 6],
+}
+
+static_assertions::const_assert_eq!(size_of::<LinearBoneHeader>(), 64);
+
+impl LinearBoneHeader {
+    /// Indexes of the values for each bone in the array starting at `index`
+    pub fn indexes<T>(&self, index: i32) -> impl Iterator<Item = usize> {
+        index_range(index, self.bone_count, size_of::<T>())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+#[allow(dead_code)]
+pub struct BoneFlexDriverHeader {
+    pub bone: i32,
+    control_count: i32,
+    control_index: i32,
+    unused: [i32; 3],
+}
+
+static_assertions::const_assert_eq!(size_of::<BoneFlexDriverHeader>(), 24);
+
+impl BoneFlexDriverHeader {
+    pub fn control_indexes(&self) -> impl Iterator<Item = usize> {
+        index_range(
+            self.control_index,
+            self.control_count,
+            size_of::<BoneFlexDriverControl>(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct BoneFlexDriverControl {
+    pub bone_component: i32, // translation along the x, y or z axis of the bone
+    pub flex_controller: i32,
+    pub min: f32, // value of the bone component that maps to 0 for the flex controller
+    pub max: f32, // value of the bone component that maps to 1 for the flex controller
+}
+
+static_assertions::const_assert_eq!(size_of::<BoneFlexDriverControl>(), 16);
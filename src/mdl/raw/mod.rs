@@ -1,11 +1,14 @@
-use crate::{index_range, FixedString};
-use crate::{Quaternion, RadianEuler, Vector};
+use crate::index_range;
+use crate::{Matrix3x4, Quaternion, RadianEuler, Vector};
 use bitflags::bitflags;
 use bytemuck::{Pod, Zeroable};
 use std::mem::size_of;
 
+pub mod animation;
+pub mod flex;
 pub mod header;
 pub mod header2;
+pub mod sequence;
 
 #[derive(Debug, Clone, Copy, Zeroable, Pod)]
 #[repr(C)]
@@ -20,7 +23,7 @@ pub struct Bone {
     pub pos_scale: Vector,
     pub rot_scale: Vector,
 
-    pub pose_to_bone: [[f32; 3]; 4], // 3x4 matrix
+    pub pose_to_bone: Matrix3x4,
     pub q_alignment: Quaternion,
     pub flags: BoneFlags,
     pub proc_type: i32,
@@ -75,11 +78,7 @@ pub struct BodyPartHeader {
 
 impl BodyPartHeader {
     pub fn model_indexes(&self) -> impl Iterator<Item = usize> {
-        index_range(
-            self.model_index,
-            self.model_count,
-            size_of::<ModelHeader>() - size_of::<FixedString<0>>(),
-        )
+        index_range(self.model_index, self.model_count, size_of::<ModelHeader>())
     }
 }
 
@@ -109,6 +108,14 @@ impl ModelHeader {
     pub fn mesh_indexes(&self) -> impl Iterator<Item = usize> {
         index_range(self.mesh_index, self.mesh_count, size_of::<MeshHeader>())
     }
+
+    pub fn eyeball_indexes(&self) -> impl Iterator<Item = usize> {
+        index_range(
+            self.eyeball_index,
+            self.eyeball_count,
+            size_of::<EyeballHeader>(),
+        )
+    }
 }
 
 #[derive(Debug, Clone, Copy, Zeroable, Pod)]
@@ -124,25 +131,215 @@ pub struct ModelVertexData {
 #[repr(C)]
 #[allow(dead_code)]
 pub struct MeshHeader {
-    material: i32,
+    pub material: i32,
     model_index: i32,
     vertex_count: i32,
     pub vertex_index: i32,
     flex_count: i32,
     flex_index: i32,
-    material_type: i32,
-    material_param: i32,
-    mesh_id: i32,
-    center: Vector,
-    vertex_data: MeshVertexData,
+    pub material_type: i32,
+    pub material_param: i32,
+    pub mesh_id: i32,
+    pub center: Vector,
+    pub vertex_data: MeshVertexData,
     padding: [i32; 8],
 }
 
+impl MeshHeader {
+    pub fn flex_indexes(&self) -> impl Iterator<Item = usize> {
+        index_range(
+            self.flex_index,
+            self.flex_count,
+            size_of::<flex::FlexHeader>(),
+        )
+    }
+}
+
 #[derive(Debug, Clone, Copy, Zeroable, Pod)]
 #[repr(C)]
 #[allow(dead_code)]
 pub struct MeshVertexData {
     // these are pointers?
     model_vertext_data: i32,
-    lod_vertex_count: [i32; 8],
+    pub lod_vertex_count: [i32; 8],
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+#[allow(dead_code)]
+pub struct TextureHeader {
+    pub name_index: i32,
+    pub flags: i32,
+    used: i32,
+    unused1: i32,
+    material: i32,        // Placeholder for mutable-void*
+    client_material: i32, // Placeholder for mutable-void*
+    unused: [i32; 10],
+}
+
+static_assertions::const_assert_eq!(size_of::<TextureHeader>(), 64);
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct HitboxSetHeader {
+    pub name_index: i32,
+    hitbox_count: i32,
+    hitbox_index: i32,
+}
+
+static_assertions::const_assert_eq!(size_of::<HitboxSetHeader>(), 12);
+
+impl HitboxSetHeader {
+    pub fn hitbox_indexes(&self) -> impl Iterator<Item = usize> {
+        index_range(
+            self.hitbox_index,
+            self.hitbox_count,
+            size_of::<HitboxHeader>(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+#[allow(dead_code)]
+pub struct HitboxHeader {
+    pub bone: i32,
+    pub group: i32, // intersection group
+    pub bb_min: Vector,
+    pub bb_max: Vector,
+    pub name_index: i32,
+    unused: [i32; 8],
+}
+
+static_assertions::const_assert_eq!(size_of::<HitboxHeader>(), 68);
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+#[allow(dead_code)]
+pub struct AttachmentHeader {
+    pub name_index: i32,
+    pub flags: u32,
+    pub local_bone: i32,
+    pub local: Matrix3x4, // attachment point
+    unused: [i32; 8],
+}
+
+static_assertions::const_assert_eq!(size_of::<AttachmentHeader>(), 92);
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct IncludeModelHeader {
+    pub label_index: i32,
+    pub name_index: i32, // file name of the included model
 }
+
+static_assertions::const_assert_eq!(size_of::<IncludeModelHeader>(), 8);
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+#[allow(dead_code)]
+pub struct EyeballHeader {
+    pub name_index: i32,
+    pub bone: i32,
+    pub origin: Vector,
+    pub z_offset: f32,
+    pub radius: f32,
+    pub up: Vector,
+    pub forward: Vector,
+    pub texture: i32,
+    unused1: i32,
+    pub iris_scale: f32,
+    unused2: i32,
+    pub upper_flex_descriptor: [i32; 3],
+    pub lower_flex_descriptor: [i32; 3],
+    pub upper_target: [f32; 3], // angle (radians) of raised, neutral, and lowered lid positions
+    pub lower_target: [f32; 3],
+    pub upper_lid_flex_descriptor: i32, // index of flex desc that actual lid flexes look to
+    pub lower_lid_flex_descriptor: i32,
+    unused: [i32; 4],
+    pub non_facs: u8, // never used by the engine
+    unused3: [u8; 3],
+    unused4: [i32; 7],
+}
+
+static_assertions::const_assert_eq!(size_of::<EyeballHeader>(), 172);
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct IkChainHeader {
+    pub name_index: i32,
+    pub link_type: i32,
+    link_count: i32,
+    link_index: i32,
+}
+
+static_assertions::const_assert_eq!(size_of::<IkChainHeader>(), 16);
+
+impl IkChainHeader {
+    pub fn link_indexes(&self) -> impl Iterator<Item = usize> {
+        index_range(self.link_index, self.link_count, size_of::<IkLinkHeader>())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+#[allow(dead_code)]
+pub struct IkLinkHeader {
+    pub bone: i32,
+    pub knee_direction: Vector, // ideal bending direction (per link, if applicable)
+    unused: Vector,
+}
+
+static_assertions::const_assert_eq!(size_of::<IkLinkHeader>(), 28);
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+#[allow(dead_code)]
+pub struct IkLockHeader {
+    pub chain: i32,
+    pub position_weight: f32,
+    pub local_rotation_weight: f32,
+    pub flags: i32,
+    unused: [i32; 4],
+}
+
+static_assertions::const_assert_eq!(size_of::<IkLockHeader>(), 32);
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+#[allow(dead_code)]
+pub struct BoneControllerHeader {
+    pub bone: i32, // -1 == 0
+    pub ty: BoneControllerFlags,
+    pub start: f32,
+    pub end: f32,
+    pub rest: i32,        // byte index value at rest
+    pub input_field: i32, // 0-3 user set controller, 4 mouth
+    unused: [i32; 8],
+}
+
+static_assertions::const_assert_eq!(size_of::<BoneControllerHeader>(), 56);
+
+bitflags! {
+    #[derive(Zeroable, Pod)]
+    #[repr(C)]
+    pub struct BoneControllerFlags: u32 {
+        const X =       0x0001;
+        const Y =       0x0002;
+        const Z =       0x0004;
+        const XR =      0x0008;
+        const YR =      0x0010;
+        const ZR =      0x0020;
+        const RLOOP =   0x8000; // controller that wraps shortest distance
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct MouthHeader {
+    pub bone: i32,
+    pub forward: Vector,
+    pub flex_descriptor: i32,
+}
+
+static_assertions::const_assert_eq!(size_of::<MouthHeader>(), 20);
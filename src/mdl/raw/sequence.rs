@@ -0,0 +1,130 @@
+use crate::mdl::raw::animation::AnimationFlags;
+use crate::{index_range, Vector};
+use bitflags::bitflags;
+use bytemuck::{Pod, Zeroable};
+use std::mem::size_of;
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+#[allow(dead_code)]
+pub struct SequenceDescriptionHeader {
+    base_pointer: i32,
+    pub label_index: i32,
+    pub activity_name_index: i32,
+    pub flags: AnimationFlags,
+    pub activity: i32, // assigned at runtime
+    pub activity_weight: i32,
+
+    event_count: i32,
+    event_index: i32,
+
+    pub bb_min: Vector,
+    pub bb_max: Vector,
+
+    pub blend_count: i32,
+    animation_index_index: i32, // short[group_size[0] * group_size[1]]
+    pub movement_index: i32,    // [blend] float array for blended movement
+    pub group_size: [i32; 2],
+    pub param_index: [i32; 2], // X, Y, Z, XR, YR, ZR
+    pub param_start: [f32; 2], // local (0..1) starting value
+    pub param_end: [f32; 2],   // local (0..1) ending value
+    pub param_parent: i32,
+
+    pub fade_in_time: f32,  // ideal cross fade in time (0.2 default)
+    pub fade_out_time: f32, // ideal cross fade out time (0.2 default)
+
+    pub local_entry_node: i32, // transition node at entry
+    pub local_exit_node: i32,  // transition node at exit
+    pub node_flags: i32,       // transition rules
+
+    pub entry_phase: f32, // used to match entry gait
+    pub exit_phase: f32,  // used to match exit gait
+
+    pub last_frame: f32, // frame that should generate EndOfSequence
+
+    pub next_sequence: i32, // auto advancing sequences
+    pub pose: i32,          // index of delta animation between end and nextseq
+
+    ik_rule_count: i32,
+
+    auto_layer_count: i32,
+    auto_layer_index: i32,
+
+    weight_list_index: i32,
+
+    pose_key_index: i32, // float[group_size[0] + group_size[1]]
+
+    ik_lock_count: i32,
+    ik_lock_index: i32,
+
+    key_value_index: i32,
+    key_value_size: i32,
+
+    cycle_pose_index: i32, // index of pose parameter to use as cycle index
+
+    unused: [i32; 7],
+}
+
+static_assertions::const_assert_eq!(size_of::<SequenceDescriptionHeader>(), 212);
+
+impl SequenceDescriptionHeader {
+    pub fn event_indexes(&self) -> impl Iterator<Item = usize> {
+        index_range(self.event_index, self.event_count, size_of::<EventHeader>())
+    }
+
+    pub fn animation_indexes(&self) -> impl Iterator<Item = usize> {
+        // overflowing grid sizes are treated as empty
+        let count = self.group_size[0]
+            .checked_mul(self.group_size[1])
+            .unwrap_or_default();
+        index_range(self.animation_index_index, count, size_of::<i16>())
+    }
+
+    /// Indexes of the bone weights, one for every bone in the model
+    pub fn weight_indexes(&self, bone_count: usize) -> impl Iterator<Item = usize> {
+        index_range(self.weight_list_index, bone_count as i32, size_of::<f32>())
+    }
+
+    pub fn pose_key_indexes(&self) -> impl Iterator<Item = usize> {
+        let count = if self.pose_key_index > 0 {
+            self.group_size[0]
+                .checked_add(self.group_size[1])
+                .unwrap_or_default()
+        } else {
+            0
+        };
+        index_range(self.pose_key_index, count, size_of::<f32>())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct EventHeader {
+    pub cycle: f32,
+    pub event: i32,
+    pub ty: EventFlags,
+    pub options: [u8; 64],
+    pub name_index: i32,
+}
+
+static_assertions::const_assert_eq!(size_of::<EventHeader>(), 80);
+
+bitflags! {
+    #[derive(Zeroable, Pod)]
+    #[repr(C)]
+    pub struct EventFlags: u32 {
+        const NEW_EVENT_STYLE = 1 << 10; // the event is identified by name instead of by id
+    }
+}
+
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct PoseParameterHeader {
+    pub name_index: i32,
+    pub flags: i32,
+    pub start: f32,      // starting value
+    pub end: f32,        // ending value
+    pub loop_range: f32, // looping range, 0 for no looping, 360 for rotations, etc.
+}
+
+static_assertions::const_assert_eq!(size_of::<PoseParameterHeader>(), 20);
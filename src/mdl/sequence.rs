@@ -0,0 +1,295 @@
+use crate::mdl::raw::animation::AnimationFlags;
+use crate::mdl::raw::sequence::{
+    EventFlags, EventHeader, PoseParameterHeader, SequenceDescriptionHeader,
+};
+use crate::mdl::{BonePose, Mdl};
+use crate::{
+    read_indexes, read_relative, read_string, FixedString, ModelError, ReadRelative, Vector,
+};
+
+type Result<T> = std::result::Result<T, ModelError>;
+
+#[derive(Debug, Clone)]
+pub struct Sequence {
+    pub label: String,
+    /// Name of the activity this sequence is used for, empty if the sequence has no activity
+    pub activity_name: String,
+    pub activity_weight: i32,
+    pub flags: AnimationFlags,
+    pub events: Vec<Event>,
+    pub bb_min: Vector,
+    pub bb_max: Vector,
+    /// Number of animations along each axis of the blend grid
+    pub group_size: [i32; 2],
+    /// Pose parameter controlling each axis of the blend grid, -1 if the axis isn't used
+    pub param_index: [i32; 2],
+    pub param_start: [f32; 2],
+    pub param_end: [f32; 2],
+    /// Animation index for each cell of the blend grid, with `group_size[0]` animations per row
+    pub animations: Vec<i16>,
+    pub fade_in_time: f32,
+    pub fade_out_time: f32,
+    /// Transition node at the start of the sequence, 0 if the sequence isn't part of a transition
+    pub entry_node: i32,
+    /// Transition node at the end of the sequence, 0 if the sequence isn't part of a transition
+    pub exit_node: i32,
+    pub node_flags: i32,
+    pub entry_phase: f32,
+    pub exit_phase: f32,
+    pub last_frame: f32,
+    pub next_sequence: i32,
+    pub pose: i32,
+    /// Blend weight for each bone in the model
+    pub weights: Vec<f32>,
+    /// Pose parameter value of each row followed by each column of the blend grid, empty if not set
+    pub pose_keys: Vec<f32>,
+}
+
+impl Sequence {
+    pub(crate) fn read(
+        data: &[u8],
+        header: SequenceDescriptionHeader,
+        bone_count: usize,
+    ) -> Result<Self> {
+        Ok(Sequence {
+            label: read_string(data, header.label_index)?,
+            activity_name: read_string(data, header.activity_name_index)?,
+            activity_weight: header.activity_weight,
+            flags: header.flags,
+            events: read_relative(data, header.event_indexes())?,
+            bb_min: header.bb_min,
+            bb_max: header.bb_max,
+            group_size: header.group_size,
+            param_index: header.param_index,
+            param_start: header.param_start,
+            param_end: header.param_end,
+            animations: read_indexes(header.animation_indexes(), data).collect::<Result<_>>()?,
+            fade_in_time: header.fade_in_time,
+            fade_out_time: header.fade_out_time,
+            entry_node: header.local_entry_node,
+            exit_node: header.local_exit_node,
+            node_flags: header.node_flags,
+            entry_phase: header.entry_phase,
+            exit_phase: header.exit_phase,
+            last_frame: header.last_frame,
+            next_sequence: header.next_sequence,
+            pose: header.pose,
+            weights: read_indexes(header.weight_indexes(bone_count), data)
+                .collect::<Result<_>>()?,
+            pose_keys: read_indexes(header.pose_key_indexes(), data).collect::<Result<_>>()?,
+        })
+    }
+
+    /// Get the index of the animation at a cell of the blend grid, clamping to the grid size
+    pub fn animation(&self, x: usize, y: usize) -> Option<usize> {
+        let columns = usize::try_from(self.group_size[0]).ok()?;
+        let rows = usize::try_from(self.group_size[1]).ok()?;
+        let x = x.min(columns.checked_sub(1)?);
+        let y = y.min(rows.checked_sub(1)?);
+        self.animations
+            .get(y * columns + x)
+            .and_then(|index| usize::try_from(*index).ok())
+    }
+
+    /// Get the pose parameter value of a blend grid cell along an axis
+    fn pose_key(&self, axis: usize, index: usize) -> f32 {
+        let offset = if axis == 0 {
+            0
+        } else {
+            self.group_size[0] as usize
+        };
+        self.pose_keys
+            .get(offset + index)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Find the blend grid cell and the interpolation factor within it along an axis
+    ///
+    /// `values` are the normalized (0..1) values for each pose parameter
+    fn blend_position(
+        &self,
+        axis: usize,
+        pose_parameters: &[PoseParameter],
+        values: &[f32],
+    ) -> (usize, f32) {
+        let Some((parameter, value)) = usize::try_from(self.param_index[axis])
+            .ok()
+            .and_then(|index| Some((pose_parameters.get(index)?, values.get(index))))
+        else {
+            return (0, 0.0);
+        };
+        let value = value.copied().unwrap_or_default();
+        let group_size = self.group_size[axis].max(1) as usize;
+        let range = parameter.end - parameter.start;
+        // degenerate ranges would spread NaN into every blended bone
+        let valid = |divisor: f32| divisor != 0.0 && divisor.is_finite();
+        if !valid(range) {
+            return (0, 0.0);
+        }
+
+        if self.pose_keys.is_empty() {
+            let local_start = (self.param_start[axis] - parameter.start) / range;
+            let local_end = (self.param_end[axis] - parameter.start) / range;
+            if !valid(local_end - local_start) {
+                return (0, 0.0);
+            }
+            let setting = ((value - local_start) / (local_end - local_start)).clamp(0.0, 1.0);
+            if group_size > 2 {
+                // estimate the cell from the evenly spaced animations
+                let scaled = setting * (group_size - 1) as f32;
+                let index = (scaled as usize).min(group_size - 2);
+                (index, scaled - index as f32)
+            } else {
+                (0, setting)
+            }
+        } else {
+            let value = value * range + parameter.start;
+            let mut index = 0;
+            loop {
+                let start = self.pose_key(axis, index);
+                let key_range = self.pose_key(axis, index + 1) - start;
+                if !valid(key_range) {
+                    return (0, 0.0);
+                }
+                let setting = (value - start) / key_range;
+                if index + 2 < group_size && setting > 1.0 {
+                    index += 1;
+                    continue;
+                }
+                break (index, setting.clamp(0.0, 1.0));
+            }
+        }
+    }
+
+    /// Get the blended local transform of every bone at a point (0..1) in the sequence cycle
+    ///
+    /// `pose_parameters` are the normalized (0..1) values for each pose parameter of the model,
+    /// missing values default to 0
+    pub fn pose(&self, mdl: &Mdl, cycle: f32, pose_parameters: &[f32]) -> Result<Vec<BonePose>> {
+        let cycle = if self.flags.contains(AnimationFlags::LOOPING) {
+            cycle - cycle.floor()
+        } else {
+            cycle.clamp(0.0, 1.0)
+        };
+        let (x, s0) = self.blend_position(0, &mdl.pose_parameters, pose_parameters);
+        let (y, s1) = self.blend_position(1, &mdl.pose_parameters, pose_parameters);
+
+        let sample = |x: usize, y: usize| {
+            let index = self.animation(x, y).ok_or(ModelError::OutOfBounds {
+                data: "SequenceAnimation",
+                offset: y * self.group_size[0].max(0) as usize + x,
+            })?;
+            let animation = mdl.animations.get(index).ok_or(ModelError::OutOfBounds {
+                data: "Animation",
+                offset: index,
+            })?;
+            let frame = cycle * (animation.frame_count - 1).max(0) as f32;
+            animation.pose(&mdl.bones, frame)
+        };
+        // blend along the x axis, only sampling the animations that contribute
+        let sample_row = |y: usize| -> Result<Vec<BonePose>> {
+            if s0 < 0.001 {
+                sample(x, y)
+            } else if s0 > 0.999 {
+                sample(x + 1, y)
+            } else {
+                Ok(blend_poses(&sample(x, y)?, &sample(x + 1, y)?, s0))
+            }
+        };
+
+        if s1 < 0.001 {
+            sample_row(y)
+        } else if s1 > 0.999 {
+            sample_row(y + 1)
+        } else {
+            Ok(blend_poses(&sample_row(y)?, &sample_row(y + 1)?, s1))
+        }
+    }
+}
+
+fn blend_poses(from: &[BonePose], to: &[BonePose], t: f32) -> Vec<BonePose> {
+    from.iter()
+        .zip(to)
+        .map(|(from, to)| from.blend(to, t))
+        .collect()
+}
+
+/// A parameter controlling the blending of sequences, such as the aim direction or movement speed
+#[derive(Debug, Clone)]
+pub struct PoseParameter {
+    pub name: String,
+    pub flags: i32,
+    pub start: f32,
+    pub end: f32,
+    /// Range over which the value wraps around, 0 if the value doesn't wrap
+    pub loop_range: f32,
+}
+
+impl ReadRelative for PoseParameter {
+    type Header = PoseParameterHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self> {
+        Ok(PoseParameter {
+            name: read_string(data, header.name_index)?,
+            flags: header.flags,
+            start: header.start,
+            end: header.end,
+            loop_range: header.loop_range,
+        })
+    }
+}
+
+impl PoseParameter {
+    /// Convert a value in the range of the parameter to the normalized (0..1) value used for blending
+    pub fn normalize(&self, value: f32) -> f32 {
+        let value = if self.loop_range != 0.0 {
+            let wrap = (self.start + self.end) / 2.0 + self.loop_range / 2.0;
+            let shift = self.loop_range - wrap;
+            value - self.loop_range * ((value + shift) / self.loop_range).floor()
+        } else {
+            value
+        };
+        if self.end == self.start {
+            return 0.0;
+        }
+        ((value - self.start) / (self.end - self.start)).clamp(0.0, 1.0)
+    }
+
+    /// Convert a normalized (0..1) value back to the range of the parameter
+    pub fn denormalize(&self, value: f32) -> f32 {
+        self.start + value * (self.end - self.start)
+    }
+}
+
+/// An event fired when the sequence reaches a point in its cycle
+#[derive(Debug, Clone)]
+pub struct Event {
+    /// Point in the cycle (0..1) at which the event fires
+    pub cycle: f32,
+    /// Numeric id of old style events
+    pub event: i32,
+    pub ty: EventFlags,
+    pub options: FixedString<64>,
+    /// Name of new style events, empty if the event is identified by id
+    pub name: String,
+}
+
+impl ReadRelative for Event {
+    type Header = EventHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self> {
+        let name = if header.name_index != 0 {
+            read_string(data, header.name_index)?
+        } else {
+            String::new()
+        };
+        Ok(Event {
+            cycle: header.cycle,
+            event: header.event,
+            ty: header.ty,
+            options: header.options.try_into()?,
+            name,
+        })
+    }
+}
@@ -0,0 +1,163 @@
+use crate::mdl::{Bone, BonePose, Mdl};
+use crate::ModelError;
+use cgmath::{Matrix4, SquareMatrix};
+
+/// The bone hierarchy of a model
+///
+/// Creating the skeleton validates that all parent references are valid, so the hierarchy
+/// can be walked without checking for cycles or missing bones
+#[derive(Debug, Clone)]
+pub struct Skeleton<'a> {
+    mdl: &'a Mdl,
+    children: Vec<Vec<usize>>,
+    roots: Vec<usize>,
+}
+
+impl<'a> Skeleton<'a> {
+    pub(crate) fn new(mdl: &'a Mdl) -> Result<Self, ModelError> {
+        let bone_count = mdl.bones.len();
+        let mut children = vec![Vec::new(); bone_count];
+        let mut roots = Vec::new();
+
+        for (index, bone) in mdl.bones.iter().enumerate() {
+            match bone.parent {
+                -1 => roots.push(index),
+                parent if parent >= 0 && (parent as usize) < bone_count => {
+                    children[parent as usize].push(index)
+                }
+                parent => {
+                    return Err(ModelError::InvalidBoneParent {
+                        bone: index,
+                        parent,
+                    })
+                }
+            }
+        }
+
+        // any bone that can't reach a root within `bone_count` steps is part of a cycle
+        for index in 0..bone_count {
+            let mut current = index;
+            let mut steps = 0;
+            while let Ok(parent) = usize::try_from(mdl.bones[current].parent) {
+                current = parent;
+                steps += 1;
+                if steps > bone_count {
+                    return Err(ModelError::BoneCycle(index));
+                }
+            }
+        }
+
+        Ok(Skeleton {
+            mdl,
+            children,
+            roots,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.mdl.bones.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mdl.bones.is_empty()
+    }
+
+    pub fn bone(&self, bone: usize) -> Option<&'a Bone> {
+        self.mdl.bones.get(bone)
+    }
+
+    pub fn name(&self, bone: usize) -> Option<&'a str> {
+        self.mdl.bone_name(bone)
+    }
+
+    pub fn parent(&self, bone: usize) -> Option<usize> {
+        usize::try_from(self.bone(bone)?.parent).ok()
+    }
+
+    pub fn children(&self, bone: usize) -> &[usize] {
+        self.children
+            .get(bone)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// All bones without a parent
+    pub fn roots(&self) -> &[usize] {
+        &self.roots
+    }
+
+    pub fn is_root(&self, bone: usize) -> bool {
+        self.bone(bone).is_some_and(|bone| bone.parent == -1)
+    }
+
+    /// Find a bone by name, bone names are compared case-insensitive like the engine does
+    pub fn find(&self, name: &str) -> Option<usize> {
+        (0..self.len()).find(|bone| {
+            self.name(*bone)
+                .is_some_and(|bone_name| bone_name.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// Iterate over the parent chain of a bone, starting with the direct parent
+    pub fn ancestors(&self, bone: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(self.parent(bone), move |bone| self.parent(*bone))
+    }
+
+    /// Iterate over all bones depth-first, every bone is visited after its parent
+    pub fn depth_first(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut stack: Vec<usize> = self.roots.iter().rev().copied().collect();
+        std::iter::from_fn(move || {
+            let bone = stack.pop()?;
+            stack.extend(self.children(bone).iter().rev());
+            Some(bone)
+        })
+    }
+
+    /// Combine the local transforms for every bone into world transforms
+    ///
+    /// Bones without a local transform are left at the transform of their parent
+    pub fn world_transforms(&self, local: &[Matrix4<f32>]) -> Vec<Matrix4<f32>> {
+        let mut world = vec![Matrix4::identity(); self.len()];
+        for bone in self.depth_first() {
+            let local = local.get(bone).copied().unwrap_or_else(Matrix4::identity);
+            world[bone] = match self.parent(bone) {
+                Some(parent) => world[parent] * local,
+                None => local,
+            };
+        }
+        world
+    }
+
+    /// Get the transforms for every bone in the bind pose
+    pub fn bind_pose(&self) -> BindPose {
+        let local: Vec<Matrix4<f32>> = self
+            .mdl
+            .bones
+            .iter()
+            .map(|bone| BonePose::from(bone).matrix())
+            .collect();
+        let world = self.world_transforms(&local);
+        let inverse_bind = self
+            .mdl
+            .bones
+            .iter()
+            .map(|bone| bone.pose_to_bone.into())
+            .collect();
+        BindPose {
+            local,
+            world,
+            inverse_bind,
+        }
+    }
+}
+
+/// Bone transforms of a model in the bind pose
+#[derive(Debug, Clone)]
+pub struct BindPose {
+    /// Transform of each bone relative to its parent
+    pub local: Vec<Matrix4<f32>>,
+    /// Transform of each bone relative to the model origin
+    pub world: Vec<Matrix4<f32>>,
+    /// Transforms from model space into the space of each bone, as stored in the model
+    pub inverse_bind: Vec<Matrix4<f32>>,
+}
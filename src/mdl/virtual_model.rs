@@ -0,0 +1,192 @@
+use crate::mdl::raw::IncludeModelHeader;
+use crate::mdl::{AnimationFlags, BonePose, Mdl, Sequence};
+use crate::{read_string, ModelError, ReadRelative};
+
+type Result<T> = std::result::Result<T, ModelError>;
+
+/// A model included with `$includemodel` to share its sequences and animations
+#[derive(Debug, Clone)]
+pub struct IncludeModel {
+    pub label: String,
+    /// File name of the included model
+    pub name: String,
+}
+
+impl ReadRelative for IncludeModel {
+    type Header = IncludeModelHeader;
+
+    fn read(data: &[u8], header: Self::Header) -> Result<Self> {
+        Ok(IncludeModel {
+            label: read_string(data, header.label_index)?,
+            name: read_string(data, header.name_index)?,
+        })
+    }
+}
+
+/// A model combined with all the models it includes
+///
+/// The sequences and animations of the included models are mapped onto the skeleton of the base model
+/// by bone name, sequences and animations with a name already used by an earlier model are skipped.
+#[derive(Debug, Clone)]
+pub struct VirtualModel {
+    /// The models making up the virtual model, the first group is the base model
+    pub groups: Vec<ModelGroup>,
+    pub sequences: Vec<VirtualItem>,
+    pub animations: Vec<VirtualItem>,
+}
+
+/// One of the models making up a virtual model
+#[derive(Debug, Clone)]
+pub struct ModelGroup {
+    pub mdl: Mdl,
+    /// Bone of the model for each bone of the base model, `None` if the model doesn't have the bone
+    pub bone_map: Vec<Option<usize>>,
+    /// Pose parameter of the base model for each pose parameter of the model
+    pub pose_parameter_map: Vec<Option<usize>>,
+}
+
+/// Reference to a sequence or animation within a group of a virtual model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtualItem {
+    pub group: usize,
+    pub index: usize,
+}
+
+impl VirtualModel {
+    /// Combine a model with all models it includes
+    ///
+    /// `resolve` is called with the file name of every included model, models included multiple times are only loaded once
+    pub fn load<F>(mdl: Mdl, mut resolve: F) -> Result<Self>
+    where
+        F: FnMut(&str) -> Result<Mdl>,
+    {
+        let mut virtual_model = VirtualModel {
+            groups: Vec::new(),
+            sequences: Vec::new(),
+            animations: Vec::new(),
+        };
+        let mut loaded: Vec<String> = Vec::new();
+        let mut pending: Vec<IncludeModel> = mdl.include_models.iter().rev().cloned().collect();
+        virtual_model.push_group(mdl);
+
+        while let Some(include) = pending.pop() {
+            let name = include.name.to_ascii_lowercase();
+            if loaded.contains(&name) {
+                continue;
+            }
+            loaded.push(name);
+            let mdl = resolve(&include.name)?;
+            pending.extend(mdl.include_models.iter().rev().cloned());
+            virtual_model.push_group(mdl);
+        }
+        Ok(virtual_model)
+    }
+
+    fn push_group(&mut self, mdl: Mdl) {
+        let group = self.groups.len();
+        let (bone_map, pose_parameter_map) = match self.groups.first() {
+            Some(base) => (
+                (0..base.mdl.bones.len())
+                    .map(|bone| {
+                        let name = base.mdl.bone_name(bone)?;
+                        (0..mdl.bones.len()).find(|bone| {
+                            mdl.bone_name(*bone)
+                                .is_some_and(|other| other.eq_ignore_ascii_case(name))
+                        })
+                    })
+                    .collect(),
+                mdl.pose_parameters
+                    .iter()
+                    .map(|parameter| base.mdl.find_pose_parameter(&parameter.name))
+                    .collect(),
+            ),
+            None => (
+                (0..mdl.bones.len()).map(Some).collect(),
+                (0..mdl.pose_parameters.len()).map(Some).collect(),
+            ),
+        };
+
+        for (index, sequence) in mdl.sequences.iter().enumerate() {
+            if self.find_sequence(&sequence.label).is_none() {
+                self.sequences.push(VirtualItem { group, index });
+            }
+        }
+        for (index, animation) in mdl.animations.iter().enumerate() {
+            let exists = self.animations.iter().any(|item| {
+                self.groups[item.group].mdl.animations[item.index]
+                    .name
+                    .eq_ignore_ascii_case(&animation.name)
+            });
+            if !exists {
+                self.animations.push(VirtualItem { group, index });
+            }
+        }
+
+        self.groups.push(ModelGroup {
+            mdl,
+            bone_map,
+            pose_parameter_map,
+        });
+    }
+
+    /// The base model
+    pub fn base(&self) -> &Mdl {
+        &self.groups[0].mdl
+    }
+
+    /// Get a sequence and the model it belongs to
+    pub fn sequence(&self, sequence: usize) -> Option<(&ModelGroup, &Sequence)> {
+        let item = self.sequences.get(sequence)?;
+        let group = &self.groups[item.group];
+        Some((group, &group.mdl.sequences[item.index]))
+    }
+
+    /// Find a sequence by its label, ignoring case
+    pub fn find_sequence(&self, label: &str) -> Option<usize> {
+        self.sequences.iter().position(|item| {
+            self.groups[item.group].mdl.sequences[item.index]
+                .label
+                .eq_ignore_ascii_case(label)
+        })
+    }
+
+    /// Get the blended local transform of every bone of the base model at a point (0..1) in the cycle of a sequence
+    ///
+    /// `pose_parameters` are the normalized (0..1) values for each pose parameter of the base model
+    pub fn sequence_pose(
+        &self,
+        sequence: usize,
+        cycle: f32,
+        pose_parameters: &[f32],
+    ) -> Result<Vec<BonePose>> {
+        let (group, sequence) = self.sequence(sequence).ok_or(ModelError::OutOfBounds {
+            data: "Sequence",
+            offset: sequence,
+        })?;
+        let pose_parameters: Vec<f32> = group
+            .pose_parameter_map
+            .iter()
+            .map(|base| {
+                base.and_then(|base| pose_parameters.get(base))
+                    .copied()
+                    .unwrap_or_default()
+            })
+            .collect();
+        let pose = sequence.pose(&group.mdl, cycle, &pose_parameters)?;
+
+        let delta = sequence.flags.contains(AnimationFlags::DELTA);
+        Ok(self
+            .base()
+            .bones
+            .iter()
+            .zip(&group.bone_map)
+            .map(
+                |(bone, mapped)| match mapped.and_then(|bone| pose.get(bone)) {
+                    Some(pose) => *pose,
+                    None if delta => BonePose::identity(),
+                    None => BonePose::from(bone),
+                },
+            )
+            .collect())
+    }
+}
@@ -1,7 +1,7 @@
 use crate::{ModelError, StringError};
 use arrayvec::ArrayString;
 use bytemuck::{Pod, Zeroable};
-use cgmath::{Deg, Euler, Rad, Vector3};
+use cgmath::{Deg, Euler, Matrix4, Rad, Vector3};
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::ops::{Add, Mul};
@@ -129,6 +129,48 @@ impl From<RadianEuler> for Euler<Deg<f32>> {
     }
 }
 
+impl From<RadianEuler> for cgmath::Quaternion<f32> {
+    fn from(e: RadianEuler) -> Self {
+        let (sy, cy) = (e.z * 0.5).sin_cos();
+        let (sp, cp) = (e.y * 0.5).sin_cos();
+        let (sr, cr) = (e.x * 0.5).sin_cos();
+        cgmath::Quaternion::new(
+            cr * cp * cy + sr * sp * sy,
+            sr * cp * cy - cr * sp * sy,
+            cr * sp * cy + sr * cp * sy,
+            cr * cp * sy - sr * sp * cy,
+        )
+    }
+}
+
+/// 3x4 transformation matrix, stored as 3 rows of 4 columns with the translation in the last column
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+#[repr(C)]
+pub struct Matrix3x4(pub [[f32; 4]; 3]);
+
+impl From<Matrix3x4> for Matrix4<f32> {
+    fn from(m: Matrix3x4) -> Self {
+        let [r0, r1, r2] = m.0;
+        // cgmath matrices are column major
+        Matrix4::new(
+            r0[0], r1[0], r2[0], 0.0, //
+            r0[1], r1[1], r2[1], 0.0, //
+            r0[2], r1[2], r2[2], 0.0, //
+            r0[3], r1[3], r2[3], 1.0,
+        )
+    }
+}
+
+impl From<Matrix4<f32>> for Matrix3x4 {
+    fn from(m: Matrix4<f32>) -> Self {
+        Matrix3x4([
+            [m.x.x, m.y.x, m.z.x, m.w.x],
+            [m.x.y, m.y.y, m.z.y, m.w.y],
+            [m.x.z, m.y.z, m.z.z, m.w.z],
+        ])
+    }
+}
+
 /// Fixed length, null-terminated string
 #[derive(Debug, Clone)]
 pub struct FixedString<const LEN: usize>(ArrayString<LEN>);
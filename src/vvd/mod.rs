@@ -1,7 +1,8 @@
 mod raw;
 
 use crate::vvd::raw::{VertexFileFixup, VvdHeader};
-use crate::{read_relative, read_relative_iter, ModelError, Readable};
+use crate::{read_relative, read_relative_iter, ModelError, Readable, Vector};
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Zero};
 pub use raw::{BoneWeight, Tangent, Vertex};
 
 type Result<T> = std::result::Result<T, ModelError>;
@@ -10,7 +11,10 @@ type Result<T> = std::result::Result<T, ModelError>;
 #[derive(Debug, Clone)]
 pub struct Vvd {
     pub header: VvdHeader,
+    /// The vertices for lod 0
     pub vertices: Vec<Vertex>,
+    /// The vertices for every lod after the first, only set if the lods use a different set of vertices
+    lod_vertices: Vec<Vec<Vertex>>,
 }
 
 impl Vvd {
@@ -23,24 +27,100 @@ impl Vvd {
                 offset: 0,
             })?,
         )?;
-        let vertices = if !header.has_fixups() {
-            source_vertices
-        } else {
-            let mut vertices = Vec::new();
-            for fixup in read_relative_iter::<'_, VertexFileFixup, _>(data, header.fixup_indexes())
-            {
-                let fixup = fixup?;
-                let from = fixup.source_vertex_id as usize;
-                let to = (fixup.source_vertex_id.saturating_add(fixup.vertex_count)) as usize;
-                vertices.extend_from_slice(source_vertices.get(from..to).ok_or({
-                    ModelError::OutOfBounds {
-                        data: "source_vertices",
-                        offset: to,
-                    }
-                })?);
+        if !header.has_fixups() {
+            return Ok(Vvd {
+                vertices: source_vertices,
+                lod_vertices: Vec::new(),
+                header,
+            });
+        }
+
+        let fixups = read_relative_iter::<'_, VertexFileFixup, _>(data, header.fixup_indexes())
+            .collect::<Result<Vec<_>>>()?;
+        let mut lods = (0..header.lod_count.clamp(1, 8))
+            .map(|lod| apply_fixups(&source_vertices, &fixups, lod))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter();
+        Ok(Vvd {
+            vertices: lods.next().unwrap_or_default(),
+            lod_vertices: lods.collect(),
+            header,
+        })
+    }
+
+    /// Get the vertices used when rendering with `lod` as the root lod
+    ///
+    /// Higher detail vertices that aren't used by the lod or any lower detail lod are left out
+    pub fn lod_vertices(&self, lod: usize) -> Option<&[Vertex]> {
+        match lod {
+            0 => Some(&self.vertices),
+            _ if (lod as i32) < self.header.lod_count => Some(
+                self.lod_vertices
+                    .get(lod - 1)
+                    .map(Vec::as_slice)
+                    .unwrap_or(&self.vertices),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// Build the vertex list for a lod from all fixups that apply to the lod
+fn apply_fixups(
+    source_vertices: &[Vertex],
+    fixups: &[VertexFileFixup],
+    lod: i32,
+) -> Result<Vec<Vertex>> {
+    let mut vertices = Vec::new();
+    for fixup in fixups.iter().filter(|fixup| fixup.lod >= lod) {
+        let from = fixup.source_vertex_id as usize;
+        let to = (fixup.source_vertex_id.saturating_add(fixup.vertex_count)) as usize;
+        vertices.extend_from_slice(source_vertices.get(from..to).ok_or({
+            ModelError::OutOfBounds {
+                data: "source_vertices",
+                offset: to,
             }
-            vertices
+        })?);
+    }
+    Ok(vertices)
+}
+
+impl Vertex {
+    /// Transform the vertex by the weighted skinning matrices of its bones
+    ///
+    /// The skinning matrices transform from the bind pose of the model into the posed model,
+    /// vertices without any weighted bones are left untransformed
+    pub fn skin(&self, skinning: &[Matrix4<f32>]) -> SkinnedVertex {
+        let weights = &self.bone_weights;
+        let count = (weights.bone_count as usize).min(weights.bone.len());
+        let matrix = weights.bone[..count]
+            .iter()
+            .zip(weights.weight)
+            .filter_map(|(bone, weight)| Some(skinning.get(*bone as usize)? * weight))
+            .fold(Matrix4::zero(), |sum, matrix| sum + matrix);
+        let matrix = if matrix.is_zero() {
+            Matrix4::identity()
+        } else {
+            matrix
+        };
+
+        let position = matrix.transform_point(Point3::from_vec(self.position.into()));
+        let normal = matrix.transform_vector(self.normal.into());
+        let normal = if normal.magnitude2() > 0.0 {
+            normal.normalize()
+        } else {
+            normal
         };
-        Ok(Vvd { vertices, header })
+        SkinnedVertex {
+            position: position.to_vec().into(),
+            normal: normal.into(),
+        }
     }
 }
+
+/// A vertex after skinning it to the pose of the model
+#[derive(Debug, Clone, Copy)]
+pub struct SkinnedVertex {
+    pub position: Vector,
+    pub normal: Vector,
+}
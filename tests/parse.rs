@@ -1,7 +1,102 @@
 use std::fs::read;
-use vmdl::mdl::Mdl;
+use vmdl::mdl::{
+    evaluate_flex_rules, BoneControllerFlags, BonePose, FlexController, FlexOp, FlexRule,
+    IncludeModel, KeyValues, Mdl, PoseParameter, VirtualModel,
+};
 use vmdl::vtx::Vtx;
 use vmdl::vvd::Vvd;
+use vmdl::{Ani, Model};
+
+fn load_model() -> Model {
+    let mdl = Mdl::read(&read("data/barrel01.mdl").unwrap()).unwrap();
+    let vtx = Vtx::read(&read("data/barrel01.dx90.vtx").unwrap()).unwrap();
+    let vvd = Vvd::read(&read("data/barrel01.vvd").unwrap()).unwrap();
+    Model::from_parts(mdl, vtx, vvd)
+}
+
+/// Append bytes to the end of the data, returning their offset
+fn append(data: &mut Vec<u8>, bytes: &[u8]) -> usize {
+    let offset = data.len();
+    data.extend_from_slice(bytes);
+    offset
+}
+
+fn append_i32s(data: &mut Vec<u8>, values: &[i32]) -> usize {
+    let bytes: Vec<u8> = values
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+    append(data, &bytes)
+}
+
+fn append_string(data: &mut Vec<u8>, string: &str) -> usize {
+    let offset = append(data, string.as_bytes());
+    data.push(0);
+    offset
+}
+
+fn patch(data: &mut [u8], offset: usize, value: i32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn read_i32(data: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn float(value: f32) -> i32 {
+    value.to_bits() as i32
+}
+
+fn i16s(values: &[i16]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+/// Header of a run of animation values
+fn run(valid: u8, total: u8) -> i16 {
+    i16::from_le_bytes([valid, total])
+}
+
+/// Animation data for bone 0 with the specified flags, followed by the data of its tracks
+fn bone_animation(flags: u8, tracks: &[u8]) -> Vec<u8> {
+    [0, flags, 0, 0]
+        .into_iter()
+        .chain(tracks.iter().copied())
+        .collect()
+}
+
+/// An animated track with values only for the x axis
+fn x_track(values: &[i16]) -> Vec<u8> {
+    i16s(
+        &[6, 0, 0]
+            .into_iter()
+            .chain(values.iter().copied())
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// The barrel model with a position scale of 0.5 for its bone, and the offsets of `count` empty
+/// animation descriptions replacing its animations
+fn animation_fixture(count: usize) -> (Vec<u8>, Vec<usize>) {
+    let mut data = read("data/barrel01.mdl").unwrap();
+    let bone = read_i32(&data, 160) as usize;
+    for axis in 0..3 {
+        patch(&mut data, bone + 72 + axis * 4, float(0.5));
+    }
+    let animations = append(&mut data, &vec![0; count * 100]);
+    patch(&mut data, 180, count as i32);
+    patch(&mut data, 184, animations as i32);
+    (data, (0..count).map(|i| animations + i * 100).collect())
+}
+
+/// Offset of the first mesh of the first model of the first body part
+fn first_mesh_offset(data: &[u8]) -> usize {
+    let body_part = read_i32(data, 236) as usize;
+    let model = body_part + read_i32(data, body_part + 12) as usize;
+    model + read_i32(data, model + 76) as usize
+}
 
 #[test]
 fn parse_mdl() {
@@ -20,3 +115,1456 @@ fn parse_vvd() {
     let data = read("data/barrel01.vvd").unwrap();
     Vvd::read(&data).unwrap();
 }
+
+#[test]
+fn parse_mdl_textures() {
+    let data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    assert_eq!(
+        vec!["barrel01"],
+        mdl.textures
+            .iter()
+            .map(|texture| texture.name.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(vec!["models\\props_badlands\\"], mdl.texture_dirs);
+}
+
+#[test]
+fn model_mesh_materials() {
+    let model = load_model();
+    assert_eq!(
+        vec![Some("barrel01")],
+        model
+            .meshes()
+            .map(|mesh| mesh.material())
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn parse_mdl_skins() {
+    let data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    assert_eq!(1, mdl.skin_table.family_count());
+    assert_eq!(Some(&[0][..]), mdl.skin_table.family(0));
+    // unknown skins fall back to the default skin
+    assert_eq!(Some(0), mdl.skin_table.texture_index(3, 0));
+
+    // the offset of later families overflows
+    let mut header = mdl.header;
+    header.skin_reference_count = i32::MAX;
+    assert_eq!(0, header.skin_reference_indexes(2).count());
+    assert_eq!(
+        Some(header.skin_reference_index as usize),
+        header.skin_reference_indexes(0).next()
+    );
+}
+
+#[test]
+fn model_body_groups() {
+    let model = load_model();
+    assert_eq!("Body", model.body_parts()[0].name);
+    let selection = model.body_group_selection(0);
+    assert_eq!(0, selection.body(model.body_parts()));
+    let indices = model.vertex_strip_indices().flatten().count();
+    assert_eq!(indices / 3, model.selected_triangles(&selection).count());
+}
+
+#[test]
+fn parse_mdl_body_part_models() {
+    let mut data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    let mesh_count = mdl.body_parts[0].models[0].meshes.len();
+
+    // replace the models of the body part with a copy of the first model and an empty model,
+    // the meshes are copied as well since offsets can't point backwards
+    let body_part = read_i32(&data, 236) as usize;
+    let model = body_part + read_i32(&data, body_part + 12) as usize;
+    let meshes = model + read_i32(&data, model + 76) as usize;
+    let first = data[model..model + 148].to_vec();
+    let models = append(&mut data, &first);
+    let second = append(&mut data, &[0; 148]);
+    let mesh_bytes = data[meshes..meshes + mesh_count * 116].to_vec();
+    let meshes = append(&mut data, &mesh_bytes);
+    patch(&mut data, models + 76, (meshes - models) as i32);
+    data[second..second + 13].copy_from_slice(b"barrel_broken");
+    patch(&mut data, second + 68, float(16.0));
+    patch(&mut data, body_part + 4, 2);
+    patch(&mut data, body_part + 12, (models - body_part) as i32);
+
+    let mdl = Mdl::read(&data).unwrap();
+    let models = &mdl.body_parts[0].models;
+    assert_eq!(2, models.len());
+    assert_eq!(mesh_count, models[0].meshes.len());
+    // the models are 148 bytes apart
+    assert_eq!("barrel_broken", models[1].name.as_str());
+    assert_eq!(16.0, models[1].bounding_radius);
+    assert!(models[1].meshes.is_empty());
+}
+
+#[test]
+fn model_lods() {
+    let model = load_model();
+    assert_eq!(model.lod_count(), model.lod(99).index() + 1);
+    assert_eq!(0, model.lod_for_metric(0.0));
+    for lod in 0..model.lod_count() {
+        let lod = model.lod(lod);
+        let vertex_count = lod.vertices().len();
+        assert!(lod
+            .vertex_strip_indices()
+            .flatten()
+            .all(|index| index < vertex_count));
+    }
+}
+
+#[test]
+fn model_lods_with_fixups() {
+    let mut mdl = read("data/barrel01.mdl").unwrap();
+    let mut vtx = read("data/barrel01.dx90.vtx").unwrap();
+    let mut vvd = read("data/barrel01.vvd").unwrap();
+
+    // add a second model without any vertices after the barrel, placed after the 698 barrel vertices
+    // and with a mesh offset that is only used when the vertices aren't truncated
+    let body_part = read_i32(&mdl, 236) as usize;
+    let model = body_part + read_i32(&mdl, body_part + 12) as usize;
+    let mesh = first_mesh_offset(&mdl);
+    let model_bytes = mdl[model..model + 148].to_vec();
+    let mesh_bytes = mdl[mesh..mesh + 116].to_vec();
+    let models = append(&mut mdl, &model_bytes);
+    let second = append(&mut mdl, &model_bytes);
+    let meshes = append(&mut mdl, &mesh_bytes);
+    let second_mesh = append(&mut mdl, &mesh_bytes);
+    patch(&mut mdl, models + 76, (meshes - models) as i32);
+    patch(&mut mdl, second + 76, (second_mesh - second) as i32);
+    patch(&mut mdl, second + 84, 698 * 48);
+    patch(&mut mdl, second_mesh + 12, 5);
+    for lod in 0..8 {
+        patch(&mut mdl, second_mesh + 52 + lod * 4, 0);
+    }
+    patch(&mut mdl, body_part + 4, 2);
+    patch(&mut mdl, body_part + 12, (models - body_part) as i32);
+
+    // the vtx model is copied together with everything after it, so the relative offsets stay valid
+    let vtx_body_part = read_i32(&vtx, 32) as usize;
+    let vtx_model = vtx_body_part + read_i32(&vtx, vtx_body_part + 4) as usize;
+    let lod_offset = read_i32(&vtx, vtx_model + 4);
+    let vtx_models = append_i32s(&mut vtx, &[0; 4]);
+    // two lods with a single mesh without any strip groups
+    let lods = append_i32s(&mut vtx, &[1, 24, float(0.0), 1, 12, float(10.0)]);
+    append(&mut vtx, &[0; 9]);
+    let copy = vtx[vtx_model..].to_vec();
+    let copy = append(&mut vtx, &copy);
+    patch(&mut vtx, vtx_models, 2);
+    patch(
+        &mut vtx,
+        vtx_models + 4,
+        copy as i32 + lod_offset - vtx_models as i32,
+    );
+    patch(&mut vtx, vtx_models + 8, 2);
+    patch(&mut vtx, vtx_models + 12, (lods - vtx_models - 8) as i32);
+    patch(&mut vtx, vtx_body_part, 2);
+    patch(
+        &mut vtx,
+        vtx_body_part + 4,
+        (vtx_models - vtx_body_part) as i32,
+    );
+
+    // lod 1 only uses the first 272 vertices
+    let fixups = append_i32s(&mut vvd, &[1, 0, 272, 0, 272, 426]);
+    patch(&mut vvd, 48, 2);
+    patch(&mut vvd, 52, fixups as i32);
+
+    let load = |mdl: &[u8]| {
+        Model::from_parts(
+            Mdl::read(mdl).unwrap(),
+            Vtx::read(&vtx).unwrap(),
+            Vvd::read(&vvd).unwrap(),
+        )
+    };
+    let offsets = |lod: vmdl::Lod| {
+        lod.meshes()
+            .map(|mesh| (mesh.model, mesh.vertex_offset))
+            .collect::<Vec<_>>()
+    };
+
+    let model = load(&mdl);
+    assert_eq!(0, model.root_lod());
+    let lod = model.lod(0);
+    assert_eq!(698, lod.vertices().len());
+    assert_eq!(vec![(0, 0), (1, 703)], offsets(lod));
+    let lod = model.lod(1);
+    assert_eq!(272, lod.vertices().len());
+    assert_eq!(vec![(0, 0), (1, 272)], offsets(lod));
+    let vertex_count = lod.vertices().len();
+    assert!(lod
+        .vertex_strip_indices()
+        .flatten()
+        .all(|index| index < vertex_count));
+
+    mdl[377] = 1;
+    let model = load(&mdl);
+    assert_eq!(1, model.root_lod());
+    assert_eq!(1, model.lod(0).index());
+    assert_eq!(272, model.lod(0).vertices().len());
+
+    // lod 1 can't be used as root lod, so the vertices aren't truncated
+    mdl[378] = 1;
+    let model = load(&mdl);
+    assert_eq!(0, model.root_lod());
+    let lod = model.lod(1);
+    assert_eq!(1, lod.index());
+    assert_eq!(698, lod.vertices().len());
+    assert_eq!(vec![(0, 0), (1, 703)], offsets(lod));
+}
+
+#[test]
+fn parse_mdl_skeleton() {
+    let data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    let skeleton = mdl.skeleton().unwrap();
+    assert_eq!(Some("static_prop"), mdl.bone_name(0));
+    assert_eq!(Some(0), skeleton.find("Static_Prop"));
+    assert_eq!(&[0], skeleton.roots());
+    assert_eq!(vec![0], skeleton.depth_first().collect::<Vec<_>>());
+}
+
+#[test]
+fn mdl_bind_pose() {
+    use cgmath::{Matrix4, SquareMatrix};
+
+    let data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    let pose = mdl.bind_pose().unwrap();
+    for (world, inverse) in pose.world.iter().zip(pose.inverse_bind.iter()) {
+        let identity: [[f32; 4]; 4] = (world * inverse).into();
+        let expected: [[f32; 4]; 4] = Matrix4::identity().into();
+        for (row, expected_row) in identity.iter().zip(expected.iter()) {
+            for (value, expected) in row.iter().zip(expected_row.iter()) {
+                assert!((value - expected).abs() < 0.001);
+            }
+        }
+    }
+}
+
+#[test]
+fn parse_mdl_attachments() {
+    use cgmath::{Deg, Matrix4, Vector3, Vector4};
+
+    let mut data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    assert!(mdl.attachments.is_empty());
+
+    // rotated 90 degrees around the z axis and moved by (1, 2, 3)
+    let local = [
+        [0.0, -1.0, 0.0, 1.0],
+        [1.0, 0.0, 0.0, 2.0],
+        [0.0, 0.0, 1.0, 3.0],
+    ];
+    let attachments = data.len();
+    data.resize(attachments + 92, 0);
+    let name = append_string(&mut data, "muzzle");
+    patch(&mut data, attachments, (name - attachments) as i32);
+    patch(&mut data, attachments + 4, 1);
+    patch(&mut data, attachments + 8, 0);
+    for (i, value) in local.iter().flatten().enumerate() {
+        patch(&mut data, attachments + 12 + i * 4, float(*value));
+    }
+    patch(&mut data, 240, 1);
+    patch(&mut data, 244, attachments as i32);
+
+    let mdl = Mdl::read(&data).unwrap();
+    assert_eq!(1, mdl.attachments.len());
+    let attachment = &mdl.attachments[0];
+    assert_eq!("muzzle", attachment.name);
+    assert_eq!(1, attachment.flags);
+    assert_eq!(0, attachment.bone);
+    assert_eq!(local, attachment.local.0);
+
+    // bone rotated 90 degrees around the x axis and moved by (10, 20, 30)
+    let bone = Matrix4::from_translation(Vector3::new(10.0, 20.0, 30.0))
+        * Matrix4::from_angle_x(Deg(90.0));
+    let world = attachment.world_transform(&[bone]).unwrap();
+    let close = |expected: Vector4<f32>, actual: Vector4<f32>| {
+        assert!((expected - actual).x.abs() < 1e-5);
+        assert!((expected - actual).y.abs() < 1e-5);
+        assert!((expected - actual).z.abs() < 1e-5);
+        assert!((expected - actual).w.abs() < 1e-5);
+    };
+    // the origin (1, 2, 3) is rotated by the bone to (1, -3, 2)
+    close(Vector4::new(11.0, 17.0, 32.0, 1.0), world.w);
+    // the x axis is rotated onto y by the attachment and onto z by the bone
+    close(Vector4::new(0.0, 0.0, 1.0, 0.0), world.x);
+    close(Vector4::new(-1.0, 0.0, 0.0, 0.0), world.y);
+    close(Vector4::new(0.0, -1.0, 0.0, 0.0), world.z);
+    assert_eq!(None, attachment.world_transform(&[]));
+}
+
+#[test]
+fn parse_mdl_hitboxes() {
+    let data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    assert_eq!(1, mdl.hitbox_sets.len());
+    let set = &mdl.hitbox_sets[0];
+    assert_eq!("default", set.name);
+    assert_eq!(1, set.hitboxes.len());
+    assert_eq!(0, set.hitboxes[0].bone);
+}
+
+#[test]
+fn model_hitbox_queries() {
+    use cgmath::{Point3, Vector3};
+    use vmdl::mdl::Ray;
+
+    let model = load_model();
+    let ray = Ray::new(Point3::new(100.0, 0.0, 40.0), Vector3::new(-1.0, 0.0, 0.0));
+    let hit = model.intersect_hitboxes(0, &ray, None).unwrap().unwrap();
+    assert_eq!(0, hit.hitbox);
+    assert!((hit.distance - (100.0 - 28.108_63)).abs() < 0.01);
+
+    let miss = Ray::new(Point3::new(100.0, 0.0, 100.0), Vector3::new(-1.0, 0.0, 0.0));
+    assert_eq!(None, model.intersect_hitboxes(0, &miss, None).unwrap());
+
+    let point = Point3::new(0.0, 0.0, 40.0);
+    assert_eq!(Some(0), model.hitbox_at_point(0, point, None).unwrap());
+}
+
+#[test]
+fn parse_mdl_animations() {
+    let data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    assert_eq!(1, mdl.animations.len());
+    let animation = &mdl.animations[0];
+    assert_eq!("@idle", animation.name);
+    assert_eq!(30.0, animation.fps);
+    assert_eq!(1, animation.frame_count);
+    assert_eq!(1, animation.sections.len());
+    assert_eq!(
+        Some(0),
+        animation.sections[0]
+            .bones
+            .as_ref()
+            .map(|bones| bones.len())
+    );
+}
+
+#[test]
+fn sample_mdl_animation() {
+    let data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    let animation = &mdl.animations[0];
+    // the idle animation doesn't animate any bones, so it should match the bind pose
+    let pose = animation.pose(&mdl.bones, 0.0).unwrap();
+    assert_eq!(1, pose.len());
+    assert_eq!(BonePose::from(&mdl.bones[0]), pose[0]);
+    assert_eq!(pose[0], animation.bone_pose(0, &mdl.bones[0], 0.5).unwrap());
+
+    let bind_pose = mdl.bind_pose().unwrap();
+    assert_eq!(bind_pose.local[0], pose[0].matrix());
+}
+
+#[test]
+fn decode_animation_values() {
+    let (mut data, animations) = animation_fixture(3);
+    let add_bones = |data: &mut Vec<u8>, animation: usize, frames: i32, bones: Vec<u8>| {
+        patch(data, animation + 16, frames);
+        let bones = append(data, &bones);
+        patch(data, animation + 56, (bones - animation) as i32);
+    };
+
+    // a run of 2 values covering 3 frames, then a run of 1 value covering 2 frames
+    let track = x_track(&[run(2, 3), 10, 20, run(1, 2), 40]);
+    add_bones(&mut data, animations[0], 5, bone_animation(0x04, &track));
+
+    // Quaternion48 of (0.5, 0, 0, w) followed by a Vector48 of (1, 2, -1)
+    let raw = i16s(&[
+        49152u16 as i16,
+        32768u16 as i16,
+        16384,
+        0x3C00,
+        0x4000,
+        0xBC00u16 as i16,
+    ]);
+    add_bones(
+        &mut data,
+        animations[1],
+        1,
+        bone_animation(0x02 | 0x01, &raw),
+    );
+
+    // Quaternion64 of (0.5, 0, 0, -w)
+    let bits: u64 = 1572864 | 1048576 << 21 | 1048576 << 42 | 1 << 63;
+    add_bones(
+        &mut data,
+        animations[2],
+        1,
+        bone_animation(0x20, &bits.to_le_bytes()),
+    );
+
+    let mdl = Mdl::read(&data).unwrap();
+    let bone = &mdl.bones[0];
+    let position = |frame: f32| {
+        let pose = mdl.animations[0].pose(&mdl.bones, frame).unwrap();
+        assert_eq!(bone.pos.y, pose[0].position.y);
+        (pose[0].position.x - bone.pos.x) / 0.5
+    };
+    let close = |expected: f32, actual: f32| assert!((expected - actual).abs() < 1e-4);
+    close(10.0, position(0.0));
+    close(20.0, position(1.0));
+    // frames after the stored values repeat the last value of the run
+    close(20.0, position(2.0));
+    close(40.0, position(3.0));
+    close(40.0, position(4.0));
+    close(15.0, position(0.5));
+    close(20.0, position(1.5));
+    // interpolating into the first value of the next run
+    close(30.0, position(2.5));
+    close(40.0, position(3.5));
+
+    let pose = mdl.animations[1].bone_pose(0, bone, 0.0).unwrap();
+    close(0.5, pose.rotation.v.x);
+    close(0.0, pose.rotation.v.y);
+    close(0.0, pose.rotation.v.z);
+    close(0.75f32.sqrt(), pose.rotation.s);
+    assert_eq!((1.0, 2.0, -1.0), pose.position.into());
+
+    let pose = mdl.animations[2].bone_pose(0, bone, 0.0).unwrap();
+    close(0.5, pose.rotation.v.x);
+    close(0.0, pose.rotation.v.y);
+    close(0.0, pose.rotation.v.z);
+    close(-(0.75f32.sqrt()), pose.rotation.s);
+    // the position isn't animated
+    assert_eq!(cgmath::Vector3::from(bone.pos), pose.position);
+}
+
+#[test]
+fn sample_sectioned_animation() {
+    // 5 frames in sections of 2, the last frame is stored in a section of its own
+    let (mut data, animations) = animation_fixture(1);
+    let animation = animations[0];
+    patch(&mut data, animation + 16, 5);
+    patch(&mut data, animation + 84, 2);
+    let sections = append_i32s(&mut data, &[0; 8]);
+    patch(&mut data, animation + 80, (sections - animation) as i32);
+    // the x position of each frame is the frame number, sections contain the frame after them
+    let section_values: [&[i16]; 4] = [&[0, 1, 2], &[2, 3, 4], &[4], &[4]];
+    for (i, values) in section_values.into_iter().enumerate() {
+        let count = values.len() as u8;
+        let track: Vec<i16> = [run(count, count)]
+            .into_iter()
+            .chain(values.iter().copied())
+            .collect();
+        let bones = append(&mut data, &bone_animation(0x04, &x_track(&track)));
+        patch(&mut data, sections + i * 8 + 4, (bones - animation) as i32);
+    }
+
+    // the data of the last section ends at the end of the file
+    let mdl = Mdl::read(&data).unwrap();
+    let animation = &mdl.animations[0];
+    assert_eq!(4, animation.sections.len());
+    let bone = &mdl.bones[0];
+    for frame in [0.0, 1.5, 2.0, 3.5, 4.0] {
+        let pose = animation.bone_pose(0, bone, frame).unwrap();
+        assert!((bone.pos.x + frame * 0.5 - pose.position.x).abs() < 1e-5);
+        assert_eq!(bone.pos.y, pose.position.y);
+    }
+}
+
+#[test]
+fn parse_mdl_sequences() {
+    let data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    assert_eq!(1, mdl.sequences.len());
+    let sequence = &mdl.sequences[0];
+    assert_eq!("idle", sequence.label);
+    assert_eq!("", sequence.activity_name);
+    assert_eq!(Some(0), mdl.find_sequence("IDLE"));
+    assert_eq!([1, 1], sequence.group_size);
+    assert_eq!([-1, -1], sequence.param_index);
+    assert_eq!(Some(0), sequence.animation(0, 0));
+    assert_eq!(Some(0), sequence.animation(3, 2));
+    assert_eq!(0.2, sequence.fade_in_time);
+    assert_eq!(0.2, sequence.fade_out_time);
+    assert_eq!(vec![1.0], sequence.weights);
+    assert!(sequence.events.is_empty());
+}
+
+#[test]
+fn parse_mdl_sequence_events() {
+    use vmdl::mdl::EventFlags;
+
+    let mut data = read("data/barrel01.mdl").unwrap();
+    let sequence = read_i32(&data, 192) as usize;
+    let events = append(&mut data, &[0; 160]);
+    // an old style event identified by its id, with the options as fixed string
+    patch(&mut data, events, float(0.25));
+    patch(&mut data, events + 4, 1004);
+    data[events + 12..events + 23].copy_from_slice(b"barrel.roll");
+    // a new style event identified by its name
+    let event = events + 80;
+    patch(&mut data, event, float(0.75));
+    patch(
+        &mut data,
+        event + 8,
+        EventFlags::NEW_EVENT_STYLE.bits() as i32,
+    );
+    data[event + 12..event + 17].copy_from_slice(b"break");
+    let name = append_string(&mut data, "AE_CL_PLAYSOUND");
+    patch(&mut data, event + 76, (name - event) as i32);
+    patch(&mut data, sequence + 24, 2);
+    patch(&mut data, sequence + 28, (events - sequence) as i32);
+
+    let mdl = Mdl::read(&data).unwrap();
+    let events = &mdl.sequences[0].events;
+    assert_eq!(2, events.len());
+    assert_eq!(0.25, events[0].cycle);
+    assert_eq!(1004, events[0].event);
+    assert!(!events[0].ty.contains(EventFlags::NEW_EVENT_STYLE));
+    assert_eq!("barrel.roll", events[0].options.as_str());
+    assert_eq!("", events[0].name);
+    assert_eq!(0.75, events[1].cycle);
+    assert!(events[1].ty.contains(EventFlags::NEW_EVENT_STYLE));
+    assert_eq!("break", events[1].options.as_str());
+    assert_eq!("AE_CL_PLAYSOUND", events[1].name);
+}
+
+#[test]
+fn parse_mdl_sequence_overflowing_group_size() {
+    let mut data = read("data/barrel01.mdl").unwrap();
+    let sequence = read_i32(&data, 192) as usize;
+    patch(&mut data, sequence + 68, 65536);
+    patch(&mut data, sequence + 72, 65536);
+    let mdl = Mdl::read(&data).unwrap();
+    assert!(mdl.sequences[0].animations.is_empty());
+
+    // both the product and the sum of the sizes overflow
+    patch(&mut data, sequence + 68, -1);
+    patch(&mut data, sequence + 72, i32::MIN);
+    patch(&mut data, sequence + 160, 4);
+    let mdl = Mdl::read(&data).unwrap();
+    assert!(mdl.sequences[0].animations.is_empty());
+    assert!(mdl.sequences[0].pose_keys.is_empty());
+}
+
+#[test]
+fn sequence_blending() {
+    let data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    assert!(mdl.pose_parameters.is_empty());
+    let pose = mdl.sequence_pose(0, 0.5, &[]).unwrap();
+    assert_eq!(vec![BonePose::from(&mdl.bones[0])], pose);
+    assert!(mdl.sequence_pose(1, 0.0, &[]).is_err());
+
+    let parameter = PoseParameter {
+        name: "aim_yaw".into(),
+        flags: 0,
+        start: -180.0,
+        end: 180.0,
+        loop_range: 360.0,
+    };
+    assert_eq!(0.5, parameter.normalize(0.0));
+    assert_eq!(0.75, parameter.normalize(90.0));
+    assert_eq!(0.75, parameter.normalize(-270.0));
+    assert_eq!(90.0, parameter.denormalize(0.75));
+}
+
+#[test]
+fn sequence_blending_degenerate_ranges() {
+    let data = read("data/barrel01.mdl").unwrap();
+    let mut mdl = Mdl::read(&data).unwrap();
+    let expected = mdl.sequence_pose(0, 0.5, &[]).unwrap();
+    mdl.pose_parameters.push(PoseParameter {
+        name: "move_x".into(),
+        flags: 0,
+        start: 1.0,
+        end: 1.0,
+        loop_range: 0.0,
+    });
+    let sequence = &mut mdl.sequences[0];
+    sequence.group_size = [2, 1];
+    sequence.animations = vec![0, 0];
+    sequence.param_index = [0, -1];
+    sequence.param_start = [0.0, 0.0];
+    sequence.param_end = [1.0, 0.0];
+
+    let finite = |pose: &[BonePose]| {
+        pose.iter().all(|bone| {
+            let rotation = bone.rotation;
+            [bone.position.x, bone.position.y, bone.position.z]
+                .into_iter()
+                .chain([rotation.s, rotation.v.x, rotation.v.y, rotation.v.z])
+                .all(f32::is_finite)
+        })
+    };
+    // the pose parameter has an empty range
+    let pose = mdl.sequence_pose(0, 0.5, &[0.5]).unwrap();
+    assert!(finite(&pose));
+    assert_eq!(expected, pose);
+
+    // the sequence has an empty range of the pose parameter
+    mdl.pose_parameters[0].end = 2.0;
+    mdl.sequences[0].param_end = [0.0, 0.0];
+    let pose = mdl.sequence_pose(0, 0.5, &[0.5]).unwrap();
+    assert!(finite(&pose));
+    assert_eq!(expected, pose);
+
+    // the pose keys of neighbouring cells are equal
+    mdl.sequences[0].pose_keys = vec![1.5, 1.5, 0.0];
+    let pose = mdl.sequence_pose(0, 0.5, &[0.5]).unwrap();
+    assert!(finite(&pose));
+    assert_eq!(expected, pose);
+}
+
+#[test]
+fn model_skinning() {
+    use cgmath::{Matrix4, Vector3};
+
+    let model = load_model();
+    let bind_pose = model.mdl().bind_pose().unwrap();
+    let skinned = model.skin_vertices(&bind_pose.world);
+    assert_eq!(model.vertices().len(), skinned.len());
+    for (vertex, skinned) in model.vertices().iter().zip(&skinned) {
+        assert!((skinned.position.x - vertex.position.x).abs() < 0.001);
+        assert!((skinned.position.y - vertex.position.y).abs() < 0.001);
+        assert!((skinned.position.z - vertex.position.z).abs() < 0.001);
+        assert!((skinned.normal.z - vertex.normal.z).abs() < 0.001);
+    }
+
+    let moved: Vec<_> = bind_pose
+        .world
+        .iter()
+        .map(|world| Matrix4::from_translation(Vector3::new(0.0, 0.0, 10.0)) * world)
+        .collect();
+    let skinned = model.skin_vertices(&moved);
+    for (vertex, skinned) in model.vertices().iter().zip(&skinned) {
+        assert!((skinned.position.z - vertex.position.z - 10.0).abs() < 0.001);
+        assert!((skinned.normal.z - vertex.normal.z).abs() < 0.001);
+    }
+}
+
+#[test]
+fn load_animation_blocks() {
+    use vmdl::mdl::AnimationBlock;
+    use vmdl::ModelError;
+
+    let data = read("data/barrel01.mdl").unwrap();
+    let mut mdl = Mdl::read(&data).unwrap();
+    assert_eq!("", mdl.animation_block_file);
+    assert!(mdl.animation_blocks.is_empty());
+    assert!(mdl.animations.iter().all(|animation| animation.is_loaded()));
+
+    let mut ani_data = vec![0; 80];
+    ani_data[0..4].copy_from_slice(b"IDAG");
+    let ani = Ani::read(&ani_data).unwrap();
+    assert_eq!(i32::from_le_bytes(*b"IDAG"), ani.header.id);
+    mdl.load_animation_blocks(&ani).unwrap();
+    assert!(Ani::read(&ani_data[..40]).is_err());
+
+    // an animation with 2 frames stored in block 1, 4 bytes after the start of the block
+    let (mut data, animations) = animation_fixture(1);
+    let animation = animations[0];
+    patch(&mut data, animation + 16, 2);
+    patch(&mut data, animation + 52, 1);
+    patch(&mut data, animation + 56, 4);
+    let name = append_string(&mut data, "models/barrel01.ani");
+    patch(&mut data, 348, name as i32);
+    let bones = bone_animation(0x04, &x_track(&[run(2, 2), 2, 4]));
+    let block_start = ani_data.len() as i32;
+    let block_end = block_start + 4 + bones.len() as i32;
+    let blocks = append_i32s(&mut data, &[0, 0, block_start, block_end]);
+    patch(&mut data, 352, 2);
+    patch(&mut data, 356, blocks as i32);
+    append(&mut ani_data, &[0; 4]);
+    append(&mut ani_data, &bones);
+
+    let mut mdl = Mdl::read(&data).unwrap();
+    assert_eq!("models/barrel01.ani", mdl.animation_block_file);
+    assert_eq!(2, mdl.animation_blocks.len());
+    assert!(!mdl.animations[0].is_loaded());
+    assert!(matches!(
+        mdl.animations[0].pose(&mdl.bones, 0.0),
+        Err(ModelError::AnimationBlockNotLoaded(1))
+    ));
+
+    // the block doesn't fit in a truncated ani file
+    let truncated = Ani::read(&ani_data[..ani_data.len() - 1]).unwrap();
+    assert!(mdl.load_animation_blocks(&truncated).is_err());
+    assert!(!mdl.animations[0].is_loaded());
+
+    let ani = Ani::read(&ani_data).unwrap();
+    assert_eq!(
+        Some(&bones[..]),
+        ani.block(&mdl.animation_blocks[1])
+            .ok()
+            .map(|block| &block[4..])
+    );
+    mdl.load_animation_blocks(&ani).unwrap();
+    assert!(mdl.animations[0].is_loaded());
+    let bone = &mdl.bones[0];
+    let pose = mdl.animations[0].pose(&mdl.bones, 1.0).unwrap();
+    assert_eq!(bone.pos.x + 2.0, pose[0].position.x);
+
+    for (data_start, data_end) in [
+        (block_end, block_start),
+        (-1, block_end),
+        (0, block_end + 1),
+    ] {
+        let block = AnimationBlock {
+            data_start,
+            data_end,
+        };
+        assert!(ani.block(&block).is_err());
+    }
+}
+
+#[test]
+fn virtual_model_includes() {
+    let data = read("data/barrel01.mdl").unwrap();
+    let mut mdl = Mdl::read(&data).unwrap();
+    assert!(mdl.include_models.is_empty());
+
+    mdl.include_models.push(IncludeModel {
+        label: String::new(),
+        name: "models/barrel01_anims.mdl".into(),
+    });
+    let mut resolved = Vec::new();
+    let virtual_model = VirtualModel::load(mdl, |name| {
+        resolved.push(name.to_string());
+        let mut included = Mdl::read(&data)?;
+        included.sequences[0].label = "roll".into();
+        Ok(included)
+    })
+    .unwrap();
+    assert_eq!(vec!["models/barrel01_anims.mdl".to_string()], resolved);
+    assert_eq!(2, virtual_model.groups.len());
+    assert_eq!(vec![Some(0)], virtual_model.groups[1].bone_map);
+    assert_eq!(2, virtual_model.sequences.len());
+    // the animation of the included model has the same name as the base animation
+    assert_eq!(1, virtual_model.animations.len());
+    assert_eq!(Some(1), virtual_model.find_sequence("Roll"));
+    let (group, sequence) = virtual_model.sequence(1).unwrap();
+    assert_eq!("roll", sequence.label);
+    assert_eq!(1, group.mdl.sequences.len());
+    let pose = virtual_model.sequence_pose(1, 0.0, &[]).unwrap();
+    assert_eq!(vec![BonePose::from(&virtual_model.base().bones[0])], pose);
+}
+
+#[test]
+fn parse_mdl_flexes() {
+    let data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    assert!(mdl.flex_descriptors.is_empty());
+    assert!(mdl.flex_controllers.is_empty());
+    assert!(mdl.flex_rules.is_empty());
+    assert!(mdl.flex_controller_uis.is_empty());
+    assert_eq!(None, mdl.find_flex_controller("jaw_drop"));
+}
+
+#[test]
+fn parse_mdl_flex_controller_uis() {
+    use vmdl::mdl::FlexRemapType;
+
+    let mut data = read("data/barrel01.mdl").unwrap();
+    let controllers = append(&mut data, &[0; 3 * 20]);
+    for (i, name) in ["jaw_drop", "smile_left", "smile_right"]
+        .into_iter()
+        .enumerate()
+    {
+        let controller = controllers + i * 20;
+        let name = append_string(&mut data, name);
+        patch(&mut data, controller + 4, (name - controller) as i32);
+    }
+    patch(&mut data, 268, 3);
+    patch(&mut data, 272, controllers as i32);
+
+    // the controller offsets are relative to each ui and point back to the controllers,
+    // the mono ui has an offset that doesn't point to a controller
+    let uis = append(&mut data, &[0; 3 * 20]);
+    let ui_values = [
+        ("jaw_drop", [Some(0), None, None], 0, 0),
+        ("smile", [Some(1), Some(2), None], 1, 1),
+        ("mouth", [Some(1), None, Some(2)], 2, 0),
+    ];
+    for (i, (name, ui_controllers, remap_type, stereo)) in ui_values.into_iter().enumerate() {
+        let ui = uis + i * 20;
+        let name = append_string(&mut data, name);
+        patch(&mut data, ui, (name - ui) as i32);
+        for (j, controller) in ui_controllers.into_iter().enumerate() {
+            let offset = controller.map_or(0, |controller| {
+                controllers as i32 + controller * 20 - ui as i32
+            });
+            patch(&mut data, ui + 4 + j * 4, offset);
+        }
+        data[ui + 16] = remap_type;
+        data[ui + 17] = stereo;
+    }
+    patch(&mut data, uis + 8, 4);
+    patch(&mut data, 384, 3);
+    patch(&mut data, 388, uis as i32);
+
+    let mdl = Mdl::read(&data).unwrap();
+    let uis = &mdl.flex_controller_uis;
+    assert_eq!(3, uis.len());
+    assert_eq!("jaw_drop", uis[0].name);
+    assert_eq!(FlexRemapType::PassThru, uis[0].remap_type);
+    assert!(!uis[0].stereo);
+    assert_eq!(Some(0), uis[0].controller);
+    assert_eq!(None, uis[0].right_controller);
+    assert_eq!(None, uis[0].value_controller);
+
+    assert_eq!("smile", uis[1].name);
+    assert_eq!(FlexRemapType::TwoWay, uis[1].remap_type);
+    assert!(uis[1].stereo);
+    assert_eq!(Some(1), uis[1].controller);
+    assert_eq!(Some(2), uis[1].right_controller);
+    assert_eq!(None, uis[1].value_controller);
+
+    assert_eq!("mouth", uis[2].name);
+    assert_eq!(FlexRemapType::NWay, uis[2].remap_type);
+    assert!(!uis[2].stereo);
+    assert_eq!(Some(1), uis[2].controller);
+    assert_eq!(None, uis[2].right_controller);
+    assert_eq!(Some(2), uis[2].value_controller);
+}
+
+#[test]
+fn flex_rules_and_morph_targets() {
+    let model = load_model();
+    assert!(model.meshes().all(|mesh| mesh.mesh.flexes.is_empty()));
+    let flexed = model.flex_vertices(&model.mdl().flex_weights(&[]));
+    assert_eq!(model.vertices().len(), flexed.len());
+    assert_eq!(model.vertices()[0].position.x, flexed[0].position.x);
+
+    let controllers = vec![
+        FlexController {
+            ty: "default".into(),
+            name: "smile".into(),
+            min: 0.0,
+            max: 1.0,
+        },
+        FlexController {
+            ty: "default".into(),
+            name: "frown".into(),
+            min: -1.0,
+            max: 1.0,
+        },
+    ];
+    let rules = vec![
+        FlexRule {
+            flex: 0,
+            ops: vec![FlexOp::Fetch1(0), FlexOp::Const(0.5), FlexOp::Mul],
+        },
+        FlexRule {
+            flex: 1,
+            ops: vec![FlexOp::TwoWay0(1)],
+        },
+        FlexRule {
+            flex: 2,
+            ops: vec![
+                FlexOp::Fetch2(0),
+                FlexOp::Fetch1(0),
+                FlexOp::Fetch2(1),
+                FlexOp::Combo(2),
+            ],
+        },
+        FlexRule {
+            flex: 3,
+            ops: vec![FlexOp::Fetch1(0), FlexOp::Fetch2(0), FlexOp::Dominate(1)],
+        },
+    ];
+    let weights = evaluate_flex_rules(&rules, &controllers, &[0.8, -0.5], 5);
+    assert_eq!(vec![0.4, 0.5, 0.4, 0.8 * (1.0 - 0.4), 0.0], weights);
+}
+
+#[test]
+fn parse_mdl_flex_bytes() {
+    let mut data = read("data/barrel01.mdl").unwrap();
+
+    // flex descriptors, offsets of the names are relative to each descriptor
+    let descriptors = data.len();
+    data.resize(descriptors + 4 * 4, 0);
+    for (i, name) in ["nway", "two_way", "lower_lid", "upper_lid"]
+        .into_iter()
+        .enumerate()
+    {
+        let name = append_string(&mut data, name);
+        patch(
+            &mut data,
+            descriptors + i * 4,
+            (name - descriptors - i * 4) as i32,
+        );
+    }
+
+    let controller_values = [
+        ("slider", 0.0, 1.0),
+        ("value", 0.0, 1.0),
+        ("close_lid", 0.0, 1.0),
+        ("blink", 0.0, 1.0),
+        ("eye_up_down", -1.0, 1.0),
+        ("close_lid_v", 0.0, 1.0),
+    ];
+    let controllers = data.len();
+    data.resize(controllers + controller_values.len() * 20, 0);
+    let ty = append_string(&mut data, "eyes");
+    for (i, (name, min, max)) in controller_values.into_iter().enumerate() {
+        let name = append_string(&mut data, name);
+        let controller = controllers + i * 20;
+        patch(&mut data, controller, (ty - controller) as i32);
+        patch(&mut data, controller + 4, (name - controller) as i32);
+        patch(&mut data, controller + 12, float(min));
+        patch(&mut data, controller + 16, float(max));
+    }
+
+    // ops as (op, data) pairs: const 1, nway 17, two way 1 16, lower eyelid 20, upper eyelid 21
+    // the eye up down, blink and close lid controllers are pushed in that order
+    let eyelid = |op| vec![(1, float(4.0)), (1, float(3.0)), (1, float(2.0)), (op, 5)];
+    let rule_ops = [
+        vec![
+            (1, float(0.0)),
+            (1, float(0.2)),
+            (1, float(0.4)),
+            (1, float(0.6)),
+            (1, float(1.0)),
+            (17, 0),
+        ],
+        vec![(16, 4)],
+        eyelid(20),
+        eyelid(21),
+    ];
+    let rules = data.len();
+    data.resize(rules + rule_ops.len() * 12, 0);
+    for (i, ops) in rule_ops.iter().enumerate() {
+        let ops: Vec<i32> = ops.iter().flat_map(|(op, data)| [*op, *data]).collect();
+        let ops = append_i32s(&mut data, &ops);
+        let rule = rules + i * 12;
+        patch(&mut data, rule, i as i32);
+        patch(&mut data, rule + 4, rule_ops[i].len() as i32);
+        patch(&mut data, rule + 8, (ops - rule) as i32);
+    }
+
+    for (offset, value) in [
+        (260, 4),
+        (264, descriptors as i32),
+        (268, controller_values.len() as i32),
+        (272, controllers as i32),
+        (276, rule_ops.len() as i32),
+        (280, rules as i32),
+    ] {
+        patch(&mut data, offset, value);
+    }
+
+    // a flex on the first mesh moving vertex 0, and a wrinkle flex moving vertices 1 and 2
+    let mesh = first_mesh_offset(&data);
+    let flexes = data.len();
+    data.resize(flexes + 2 * 60, 0);
+    let vertex_animation = |index: u16, delta: [u16; 3], wrinkle: Option<i16>| {
+        let mut bytes: Vec<u8> = [index, 0xFF00]
+            .into_iter()
+            .chain(delta)
+            .chain([0; 3])
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        bytes.extend(wrinkle.map(i16::to_le_bytes).into_iter().flatten());
+        bytes
+    };
+    // float16 1.0, 2.0 and -1.0
+    let vertices = append(
+        &mut data,
+        &vertex_animation(0, [0x3C00, 0x4000, 0xBC00], None),
+    );
+    let wrinkle_vertices = data.len();
+    append(&mut data, &vertex_animation(1, [0x3C00; 3], Some(16383)));
+    append(&mut data, &vertex_animation(2, [0x4000; 3], Some(-32767)));
+    for (i, (vertex_index, count, ty)) in [(vertices, 1, 0), (wrinkle_vertices, 2, 1)]
+        .into_iter()
+        .enumerate()
+    {
+        let flex = flexes + i * 60;
+        patch(&mut data, flex, 1);
+        for (j, target) in [0.0, 1.0, 1.0, 2.0].into_iter().enumerate() {
+            patch(&mut data, flex + 4 + j * 4, float(target));
+        }
+        patch(&mut data, flex + 20, count);
+        patch(&mut data, flex + 24, (vertex_index - flex) as i32);
+        data[flex + 32] = ty;
+    }
+    patch(&mut data, mesh + 16, 2);
+    patch(&mut data, mesh + 20, (flexes - mesh) as i32);
+
+    let mdl = Mdl::read(&data).unwrap();
+    assert_eq!("upper_lid", mdl.flex_descriptors[3].name);
+    assert_eq!(Some(4), mdl.find_flex_controller("EYE_UP_DOWN"));
+    assert_eq!("eyes", mdl.flex_controllers[4].ty);
+    assert_eq!(-1.0, mdl.flex_controllers[4].min);
+    assert_eq!(FlexOp::NWay(0), *mdl.flex_rules[0].ops.last().unwrap());
+    assert_eq!(vec![FlexOp::TwoWay1(4)], mdl.flex_rules[1].ops);
+    assert_eq!(FlexOp::DmeLowerEyelid(5), mdl.flex_rules[2].ops[3]);
+    assert_eq!(FlexOp::DmeUpperEyelid(5), mdl.flex_rules[3].ops[3]);
+
+    let weights = mdl.flex_weights(&[0.8, 0.1, 0.8, 1.0, 0.5, 0.25]);
+    let expected = [0.4, 0.5, 0.5 * 0.75 * 0.8, 0.25 * 0.8];
+    for (expected, weight) in expected.into_iter().zip(&weights) {
+        assert!((expected - weight).abs() < 1e-6);
+    }
+
+    let flexes = &mdl.body_parts[0].models[0].meshes[0].flexes;
+    assert_eq!(2, flexes.len());
+    assert_eq!(1, flexes[0].flex_descriptor);
+    assert_eq!(255, flexes[0].vertices[0].side);
+    let delta = &flexes[0].vertices[0].position;
+    assert_eq!((1.0, 2.0, -1.0), (delta.x, delta.y, delta.z));
+    // wrinkle vertices are 18 bytes apart
+    let wrinkle = &flexes[1].vertices;
+    assert_eq!(
+        vec![1, 2],
+        wrinkle.iter().map(|v| v.index).collect::<Vec<_>>()
+    );
+    assert_eq!(2.0, wrinkle[1].position.z);
+    assert!((wrinkle[0].wrinkle - 0.5).abs() < 1e-4);
+    assert_eq!(-1.0, wrinkle[1].wrinkle);
+
+    let vtx = Vtx::read(&read("data/barrel01.dx90.vtx").unwrap()).unwrap();
+    let vvd = Vvd::read(&read("data/barrel01.vvd").unwrap()).unwrap();
+    let model = Model::from_parts(mdl, vtx, vvd);
+    // the two way flex has a weight of 0.5, which the ramp maps to 0.5
+    let flexed = model.flex_vertices(&weights);
+    let offset = model.meshes().next().unwrap().vertex_offset;
+    let (original, flexed) = (&model.vertices()[offset], &flexed[offset]);
+    assert!((original.position.y + 1.0 - flexed.position.y).abs() < 1e-4);
+    assert!((original.position.z - 0.5 - flexed.position.z).abs() < 1e-4);
+
+    // with the fixed point flag the deltas are scaled integers
+    let flags = read_i32(&data, 152);
+    patch(&mut data, 152, flags | 0x00200000);
+    patch(&mut data, 392, float(0.5));
+    let mdl = Mdl::read(&data).unwrap();
+    let flexes = &mdl.body_parts[0].models[0].meshes[0].flexes;
+    let delta = &flexes[0].vertices[0].position;
+    assert_eq!(
+        (
+            0x3C00 as f32 * 0.5,
+            0x4000 as f32 * 0.5,
+            0xBC00u16 as i16 as f32 * 0.5
+        ),
+        (delta.x, delta.y, delta.z)
+    );
+    assert_eq!(16383.0 * 0.5, flexes[1].vertices[0].wrinkle);
+}
+
+#[test]
+fn flex_rule_nway() {
+    let controllers: Vec<FlexController> = ["slider", "value"]
+        .into_iter()
+        .map(|name| FlexController {
+            ty: "default".into(),
+            name: name.into(),
+            min: 0.0,
+            max: 1.0,
+        })
+        .collect();
+    // ramp 0.0, 0.2, 0.4, 0.6 with controller 1 as the value controller, scaling controller 0
+    let rule = FlexRule {
+        flex: 0,
+        ops: vec![
+            FlexOp::Const(0.0),
+            FlexOp::Const(0.2),
+            FlexOp::Const(0.4),
+            FlexOp::Const(0.6),
+            FlexOp::Const(1.0),
+            FlexOp::NWay(0),
+        ],
+    };
+    // rising edge: (0.1 - 0.0) / (0.2 - 0.0) = 0.5, times the slider value 0.8
+    let weight = rule.evaluate(&controllers, &[0.8, 0.1], &[]);
+    assert!((weight - 0.4).abs() < 1e-6);
+    // plateau
+    let weight = rule.evaluate(&controllers, &[0.8, 0.3], &[]);
+    assert!((weight - 0.8).abs() < 1e-6);
+    // falling edge: (0.6 - 0.5) / (0.6 - 0.4) = 0.5
+    let weight = rule.evaluate(&controllers, &[0.8, 0.5], &[]);
+    assert!((weight - 0.4).abs() < 1e-6);
+    // outside of the ramp
+    assert_eq!(0.0, rule.evaluate(&controllers, &[0.8, 0.7], &[]));
+}
+
+#[test]
+fn flex_rule_eyelids() {
+    let controllers: Vec<FlexController> = [
+        ("close_lid_v", 0.0, 1.0),
+        ("close_lid", 0.0, 1.0),
+        ("blink", 0.0, 1.0),
+        ("eye_up_down", -1.0, 1.0),
+    ]
+    .into_iter()
+    .map(|(name, min, max)| FlexController {
+        ty: "eyes".into(),
+        name: name.into(),
+        min,
+        max,
+    })
+    .collect();
+    // the eye up down, blink and close lid controllers are pushed in that order
+    let rule = |op: FlexOp| FlexRule {
+        flex: 0,
+        ops: vec![
+            FlexOp::Const(3.0),
+            FlexOp::Const(2.0),
+            FlexOp::Const(1.0),
+            op,
+        ],
+    };
+    let lower = rule(FlexOp::DmeLowerEyelid(0));
+    let upper = rule(FlexOp::DmeUpperEyelid(0));
+    let evaluate = |rule: &FlexRule, eye_up_down: f32| {
+        rule.evaluate(&controllers, &[0.25, 0.8, 1.0, eye_up_down], &[])
+    };
+    let close = |expected: f32, actual: f32| assert!((expected - actual).abs() < 1e-6);
+
+    // looking up lowers the weight of the lower lid
+    close((1.0 - 0.5) * (1.0 - 0.25) * 0.8, evaluate(&lower, 0.5));
+    close((1.0 - 0.25) * 0.8, evaluate(&lower, -0.5));
+    // looking down lowers the weight of the upper lid
+    close(0.25 * 0.8, evaluate(&upper, 0.5));
+    close((1.0 - 0.5) * 0.25 * 0.8, evaluate(&upper, -0.5));
+}
+
+#[test]
+fn parse_mdl_eyeballs() {
+    let model = load_model();
+    let models = &model.body_parts()[0].models;
+    assert!(models.iter().all(|model| model.eyeballs.is_empty()));
+    assert!(model.meshes().all(|mesh| !mesh.is_eyes()));
+    assert!(model.meshes().all(|mesh| mesh.eyeball().is_none()));
+    assert_eq!(None, models[0].meshes[0].eyeball());
+
+    let mut data = read("data/barrel01.mdl").unwrap();
+    let body_part = read_i32(&data, 236) as usize;
+    let model = body_part + read_i32(&data, body_part + 12) as usize;
+    let eyeball = append(&mut data, &[0; 172]);
+    let fields = [
+        (8, float(1.0)),
+        (12, float(2.0)),
+        (16, float(3.0)),
+        (20, float(0.5)),
+        (24, float(0.25)),
+        (36, float(1.0)),
+        (40, float(1.0)),
+        (52, 2),
+        (60, float(1.5)),
+        (92, float(-0.5)),
+        (100, float(0.5)),
+        (104, float(-0.25)),
+        (112, float(0.25)),
+        (116, 7),
+        (120, 8),
+        (140, 1),
+    ];
+    for (offset, value) in fields {
+        patch(&mut data, eyeball + offset, value);
+    }
+    for (index, descriptor) in (1..=6).enumerate() {
+        patch(&mut data, eyeball + 68 + index * 4, descriptor);
+    }
+    let name = append_string(&mut data, "eye_right");
+    patch(&mut data, eyeball, (name - eyeball) as i32);
+    patch(&mut data, model + 100, 1);
+    patch(&mut data, model + 104, (eyeball - model) as i32);
+    // render the barrel mesh as the eyeball
+    let mesh_offset = first_mesh_offset(&data);
+    patch(&mut data, mesh_offset + 24, 1);
+    patch(&mut data, mesh_offset + 28, 0);
+
+    let mdl = Mdl::read(&data).unwrap();
+    let eyeball = &mdl.body_parts[0].models[0].eyeballs[0];
+    assert_eq!("eye_right", eyeball.name);
+    assert_eq!(0, eyeball.bone);
+    assert_eq!(
+        [1.0, 2.0, 3.0],
+        [eyeball.origin.x, eyeball.origin.y, eyeball.origin.z]
+    );
+    assert_eq!(0.5, eyeball.z_offset);
+    assert_eq!(0.25, eyeball.radius);
+    assert_eq!(1.0, eyeball.up.z);
+    assert_eq!(1.0, eyeball.forward.x);
+    assert_eq!(2, eyeball.texture);
+    assert_eq!(1.5, eyeball.iris_scale);
+    assert_eq!([1, 2, 3], eyeball.upper_flex_descriptors);
+    assert_eq!([4, 5, 6], eyeball.lower_flex_descriptors);
+    assert_eq!([-0.5, 0.0, 0.5], eyeball.upper_targets);
+    assert_eq!([-0.25, 0.0, 0.25], eyeball.lower_targets);
+    assert_eq!(7, eyeball.upper_lid_flex_descriptor);
+    assert_eq!(8, eyeball.lower_lid_flex_descriptor);
+    assert!(eyeball.non_facs);
+    assert_eq!(Some(0), mdl.body_parts[0].models[0].meshes[0].eyeball());
+
+    let vtx = Vtx::read(&read("data/barrel01.dx90.vtx").unwrap()).unwrap();
+    let vvd = Vvd::read(&read("data/barrel01.vvd").unwrap()).unwrap();
+    let model = Model::from_parts(mdl, vtx.clone(), vvd.clone());
+    let mesh = model.meshes().next().unwrap();
+    assert!(mesh.is_eyes());
+    assert_eq!(
+        Some("eye_right"),
+        mesh.eyeball().map(|eyeball| eyeball.name.as_str())
+    );
+
+    // a mesh referencing a missing eyeball is still rendered as an eye
+    patch(&mut data, mesh_offset + 28, 1);
+    let model = Model::from_parts(Mdl::read(&data).unwrap(), vtx, vvd);
+    let mesh = model.meshes().next().unwrap();
+    assert!(mesh.is_eyes());
+    assert!(mesh.eyeball().is_none());
+}
+
+#[test]
+fn parse_mdl_ik() {
+    use vmdl::ModelError;
+
+    let mut data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    assert!(mdl.ik_chains.is_empty());
+    assert!(mdl.ik_locks.is_empty());
+    mdl.validate_ik().unwrap();
+
+    // a chain with two links, offsets of the name and links are relative to the chain
+    let chain = append_i32s(&mut data, &[0, 1, 2, 0]);
+    let name = append_string(&mut data, "leg");
+    let links = append_i32s(
+        &mut data,
+        &[
+            0,
+            float(1.0),
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            float(0.0),
+            float(0.0),
+            float(-1.0),
+            0,
+            0,
+            0,
+        ],
+    );
+    patch(&mut data, chain, (name - chain) as i32);
+    patch(&mut data, chain + 12, (links - chain) as i32);
+    let lock = append_i32s(&mut data, &[0, float(1.0), float(0.5), 2, 0, 0, 0, 0]);
+    for (offset, value) in [(284, 1), (288, chain as i32), (320, 1), (324, lock as i32)] {
+        patch(&mut data, offset, value);
+    }
+
+    let mut mdl = Mdl::read(&data).unwrap();
+    assert_eq!(1, mdl.ik_chains.len());
+    let chain = &mdl.ik_chains[0];
+    assert_eq!("leg", chain.name);
+    assert_eq!(1, chain.link_type);
+    assert_eq!(2, chain.links.len());
+    assert_eq!(1.0, chain.links[0].knee_direction.x);
+    assert_eq!(-1.0, chain.links[1].knee_direction.z);
+    assert_eq!(Some(0), chain.end_bone());
+    assert_eq!(1, mdl.ik_locks.len());
+    let lock = &mdl.ik_locks[0];
+    assert_eq!(0, lock.chain);
+    assert_eq!(1.0, lock.position_weight);
+    assert_eq!(0.5, lock.local_rotation_weight);
+    assert_eq!(2, lock.flags);
+    mdl.validate_ik().unwrap();
+
+    mdl.ik_locks[0].chain = 1;
+    assert!(matches!(
+        mdl.validate_ik(),
+        Err(ModelError::InvalidIkLockChain { lock: 0, chain: 1 })
+    ));
+    mdl.ik_chains[0].links[1].bone = mdl.bones.len() as i32;
+    assert!(matches!(
+        mdl.validate_ik(),
+        Err(ModelError::InvalidIkChainBone { chain: 0, bone }) if bone == mdl.bones.len() as i32
+    ));
+}
+
+#[test]
+fn parse_mdl_bone_controllers() {
+    let mut data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    assert!(mdl.bone_controllers.is_empty());
+    assert!(mdl.mouths.is_empty());
+    assert_eq!([-1; 6], mdl.bones[0].bone_controller);
+    assert_eq!(0, mdl.bone_controllers_for_bone(0).count());
+
+    // a translation controller along x and a rotation controller around z, both on bone 0
+    let controllers = append_i32s(&mut data, &[0, 0x0001, float(-2.0), float(2.0), 128, 1]);
+    append_i32s(&mut data, &[0; 8]);
+    append_i32s(
+        &mut data,
+        &[0, 0x0020 | 0x8000, float(-90.0), float(90.0), 0, 4],
+    );
+    append_i32s(&mut data, &[0; 8]);
+    let mouth = append_i32s(&mut data, &[0, 0, float(1.0), 0, 3]);
+    for (offset, value) in [
+        (164, 2),
+        (168, controllers as i32),
+        (292, 1),
+        (296, mouth as i32),
+    ] {
+        patch(&mut data, offset, value);
+    }
+    // link the controllers to the x and zr axes of the bone
+    let bone = read_i32(&data, 160) as usize;
+    patch(&mut data, bone + 8, 0);
+    patch(&mut data, bone + 8 + 5 * 4, 1);
+
+    let mdl = Mdl::read(&data).unwrap();
+    assert_eq!(2, mdl.bone_controllers.len());
+    let translation = &mdl.bone_controllers[0];
+    assert_eq!(0, translation.bone);
+    assert_eq!(BoneControllerFlags::X, translation.ty);
+    assert_eq!((-2.0, 2.0), (translation.start, translation.end));
+    assert_eq!(128, translation.rest);
+    assert_eq!(1, translation.input_field);
+    let rotation = &mdl.bone_controllers[1];
+    assert_eq!(
+        BoneControllerFlags::ZR | BoneControllerFlags::RLOOP,
+        rotation.ty
+    );
+    assert_eq!(4, rotation.input_field);
+
+    let controllers: Vec<_> = mdl.bone_controllers_for_bone(0).collect();
+    assert_eq!(2, controllers.len());
+    assert_eq!(0, controllers[0].0);
+    assert_eq!(BoneControllerFlags::X, controllers[0].1.ty);
+    assert_eq!(5, controllers[1].0);
+    assert_eq!(-90.0, controllers[1].1.start);
+    assert_eq!(0, mdl.bone_controllers_for_bone(1).count());
+
+    assert_eq!(1, mdl.mouths.len());
+    assert_eq!(0, mdl.mouths[0].bone);
+    assert_eq!(1.0, mdl.mouths[0].forward.y);
+    assert_eq!(3, mdl.mouths[0].flex_descriptor);
+}
+
+#[test]
+fn parse_mdl_keyvalues() {
+    let data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    assert_eq!("", mdl.keyvalues_text());
+    assert!(mdl.keyvalues().unwrap().is_empty());
+
+    let text = r#"
+        "prop_data" { "base" "Wooden.Medium" health 50 }
+        // comment
+        "physgun_interactions" { "onfirstimpact" "break" }
+        "fire_interactions" { "explosive_resist" "models\props\gibs.mdl" }
+    "#;
+    let key_values = KeyValues::parse(text).unwrap();
+    let prop_data = key_values.get_block("PROP_DATA").unwrap();
+    assert_eq!(Some("Wooden.Medium"), prop_data.get_str("base"));
+    assert_eq!(Some("50"), prop_data.get_str("health"));
+    assert_eq!(
+        Some("break"),
+        key_values
+            .get_block("physgun_interactions")
+            .and_then(|block| block.get_str("onfirstimpact"))
+    );
+    assert_eq!(
+        Some(r"models\props\gibs.mdl"),
+        key_values
+            .get_block("fire_interactions")
+            .and_then(|block| block.get_str("explosive_resist"))
+    );
+    assert_eq!(3, key_values.iter().count());
+
+    assert!(KeyValues::parse(r#""prop_data" { "base" "#).is_err());
+    assert!(KeyValues::parse(r#""prop_data" { } }"#).is_err());
+}
+
+#[test]
+fn parse_mdl_header2() {
+    let mut data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    let header2 = mdl.header2.unwrap();
+    assert_eq!(r"props_badlands\barrel01.mdl", mdl.name);
+    assert!((header2.max_eye_deflection() - 30f32.to_radians().cos()).abs() < 1e-6);
+    assert!(mdl.source_bone_transforms.is_empty());
+    assert!(mdl.linear_bones.is_none());
+    assert!(mdl.bone_flex_drivers.is_empty());
+
+    // append the sections to the file, offsets are relative to the secondary header
+    let base = mdl.header.header2_index().unwrap();
+    let long_name = format!("props_badlands/{}.mdl", "barrel".repeat(12));
+    let name = append_string(&mut data, &long_name);
+
+    let one = float(1.0);
+    let linear_bones = append_i32s(
+        &mut data,
+        &[1, 64, 68, 72, 84, 100, 112, 160, 172, 184, 0, 0, 0, 0, 0, 0],
+    );
+    append_i32s(&mut data, &[0x100, -1]);
+    append_i32s(&mut data, &[one, float(2.0), float(3.0)]);
+    append_i32s(&mut data, &[0, 0, 0, one]);
+    append_i32s(&mut data, &[0; 3]);
+    append_i32s(&mut data, &[one, 0, 0, 0, 0, one, 0, 0, 0, 0, one, 0]);
+    append_i32s(&mut data, &[one; 3]);
+    append_i32s(&mut data, &[one; 3]);
+    append_i32s(&mut data, &[0, 0, 0, one]);
+
+    let drivers = append_i32s(&mut data, &[0, 1, 24, 0, 0, 0]);
+    append_i32s(&mut data, &[2, 0, 0, one]);
+
+    patch(&mut data, base + 16, (linear_bones - base) as i32);
+    patch(&mut data, base + 20, (name - base) as i32);
+    patch(&mut data, base + 24, 1);
+    patch(&mut data, base + 28, (drivers - base) as i32);
+
+    let mdl = Mdl::read(&data).unwrap();
+    assert_eq!(long_name, mdl.name);
+    let linear_bones = mdl.linear_bones.unwrap();
+    assert_eq!(1, linear_bones.len());
+    assert_eq!(vec![-1], linear_bones.parents);
+    assert_eq!(2.0, linear_bones.positions[0].y);
+    assert_eq!(1.0, linear_bones.quaternions[0].w);
+    assert_eq!(1.0, linear_bones.pose_to_bone[0].0[2][2]);
+    assert_eq!(1.0, linear_bones.q_alignments[0].w);
+    assert_eq!(1, mdl.bone_flex_drivers.len());
+    let driver = &mdl.bone_flex_drivers[0];
+    assert_eq!(0, driver.bone);
+    assert_eq!(1, driver.controls.len());
+    assert_eq!(2, driver.controls[0].bone_component);
+    assert_eq!(1.0, driver.controls[0].max);
+}
+
+#[test]
+fn parse_mdl_transition_nodes() {
+    let mut data = read("data/barrel01.mdl").unwrap();
+    let mdl = Mdl::read(&data).unwrap();
+    assert!(mdl.node_names.is_empty());
+    assert_eq!(None, mdl.transition(1, 1));
+
+    // append two nodes, where each can transition directly to the other
+    let name_offsets = append_i32s(&mut data, &[0; 2]);
+    for (i, name) in ["standing", "crouching"].into_iter().enumerate() {
+        let name = append_string(&mut data, name);
+        patch(&mut data, name_offsets + i * 4, name as i32);
+    }
+    let table = append(&mut data, &[0, 2, 1, 0]);
+    for (offset, value) in [(248, 2), (252, table), (256, name_offsets)] {
+        patch(&mut data, offset, value as i32);
+    }
+
+    let mdl = Mdl::read(&data).unwrap();
+    assert_eq!(vec!["standing", "crouching"], mdl.node_names);
+    assert_eq!(Some(2), mdl.transition(1, 2));
+    assert_eq!(Some(1), mdl.transition(2, 1));
+    assert_eq!(None, mdl.transition(1, 1));
+    assert_eq!(None, mdl.transition(0, 1));
+    assert_eq!(None, mdl.transition(1, 3));
+
+    // the size of the transition table overflows and is treated as empty
+    let name = read_i32(&data, name_offsets);
+    let names = append_i32s(&mut data, &[name; 65536]);
+    patch(&mut data, 248, 65536);
+    patch(&mut data, 256, names as i32);
+    let mdl = Mdl::read(&data).unwrap();
+    assert_eq!(65536, mdl.node_names.len());
+    assert_eq!(None, mdl.transition(1, 2));
+}